@@ -1,4 +1,5 @@
 use average::{Estimate, MeanWithError};
+use display::layout::Orientation;
 use dummy_pin::DummyPin;
 use esp_idf_svc::hal::{
     adc::*,
@@ -13,7 +14,7 @@ use esp_idf_svc::hal::{
 use interp::InterpMode;
 use it8951::{
     interface::{IT8951Interface, IT8951SPIInterface},
-    memory_converter_settings::MemoryConverterSetting,
+    memory_converter_settings::{MemoryConverterRotation, MemoryConverterSetting},
     Config, Run, IT8951,
 };
 use oneshot::{AdcChannelDriver, AdcDriver};
@@ -157,7 +158,10 @@ impl<'a> BatteryVoltageSensor<'a> {
     }
 }
 
-pub fn display_create(peripherals: &mut DisplayPerepherials) -> Result<M5Display, EspError> {
+pub fn display_create(
+    peripherals: &mut DisplayPerepherials,
+    orientation: Orientation,
+) -> Result<M5Display, EspError> {
     log::info!("Initializing display");
     let spi = SpiDeviceDriver::new_single(
         &mut peripherals.spi,
@@ -184,7 +188,7 @@ pub fn display_create(peripherals: &mut DisplayPerepherials) -> Result<M5Display
         display_interface,
         Config::default(),
         MemoryConverterSetting {
-            rotation: it8951::memory_converter_settings::MemoryConverterRotation::Rotate90,
+            rotation: memory_converter_rotation(orientation),
             ..Default::default()
         },
     )
@@ -195,3 +199,12 @@ pub fn display_create(peripherals: &mut DisplayPerepherials) -> Result<M5Display
 
     Ok(epd)
 }
+
+fn memory_converter_rotation(orientation: Orientation) -> MemoryConverterRotation {
+    match orientation {
+        Orientation::Landscape => MemoryConverterRotation::Rotate0,
+        Orientation::Portrait => MemoryConverterRotation::Rotate90,
+        Orientation::LandscapeFlipped => MemoryConverterRotation::Rotate180,
+        Orientation::PortraitFlipped => MemoryConverterRotation::Rotate270,
+    }
+}
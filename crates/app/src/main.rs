@@ -2,12 +2,21 @@
 mod hardware;
 mod network;
 mod state_container;
+mod storage;
 mod ui;
 
 use core::str;
-use std::{num::NonZeroU32, thread, time::Instant};
+use std::{
+    num::NonZeroU32,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use embassy_futures::select::select3;
+use display::buttons::Button;
+use display::layout::{Profile, ScreenLayout};
+use display::state::SetpointStatus;
+use embassy_futures::select::{select, select4, Either};
+use embassy_sync::lazy_lock::LazyLock;
 use esp_idf_svc::{
     eventloop::EspSystemEventLoop,
     hal::{
@@ -27,6 +36,7 @@ use hardware::*;
 use network::network_loop;
 use simple_moving_average::{SumTreeSMA, SMA};
 use state_container::{StateStoreExt, STATE_STORE};
+use storage::StateStorage;
 use ui::display_loop;
 use uom::si::electric_potential::volt;
 
@@ -44,10 +54,42 @@ pub struct Config {
     mqtt_server: &'static str,
     #[default("")]
     mqtt_sensor_topic: &'static str,
+    /// Logs every displayed state as a replayable record, see `display::recording`
+    #[default(false)]
+    record_state: bool,
+    /// Publishes the same records to this topic when set
+    #[default("")]
+    mqtt_record_topic: &'static str,
+    /// Local time offset of the idle screen clock
+    #[default(0)]
+    utc_offset_minutes: i32,
+    /// Retained JSON state, see `display::state::StateReport`; empty disables it
+    #[default("m5premote/diagnostics")]
+    mqtt_diagnostics_topic: &'static str,
+    #[default(300)]
+    diagnostics_interval_secs: u64,
 }
 
 pub static APP_CONFIG: Config = CONFIG;
 
+/// Screen layout and temperature unit, embedded from `layout.toml` next to `cfg.toml`.
+/// Building with `--features production` forces the production profile.
+pub static SCREEN_LAYOUT: LazyLock<ScreenLayout> = LazyLock::new(|| {
+    let mut layout = ScreenLayout::from_toml(include_str!("../../../layout.toml"))
+        .expect("Invalid screen layout");
+    if cfg!(feature = "production") {
+        layout.profile = Profile::Production;
+    }
+    layout
+});
+
+/// `AppState::time_since_boot` is measured from here
+static BOOT: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+fn time_since_boot() -> Duration {
+    Instant::now() - *BOOT.get()
+}
+
 fn main() -> Result<(), EspError> {
     // It is necessary to call this function once. Otherwise some patches to the runtime
     // implemented by esp-idf-sys might not link properly. See https://github.com/esp-rs/esp-idf-template/issues/71
@@ -55,6 +97,7 @@ fn main() -> Result<(), EspError> {
 
     // Bind the log crate to the ESP Logging facilities
     esp_idf_svc::log::EspLogger::initialize_default();
+    BOOT.get();
 
     let sys_loop = EspSystemEventLoop::take().unwrap();
     let timer_service = EspTimerService::new().unwrap();
@@ -119,6 +162,9 @@ fn main() -> Result<(), EspError> {
 
     log::info!("Configuration: {:?}", APP_CONFIG);
 
+    let mut storage = StateStorage::new(nvs.clone())?;
+    esp_idf_svc::hal::task::block_on(storage.restore());
+
     {
         thread::spawn(move || {
             let r = button_thread(buttons);
@@ -131,22 +177,30 @@ fn main() -> Result<(), EspError> {
     esp_idf_svc::hal::task::block_on(async {
         log::info!("Initialization complete");
 
-        let result = select3(
+        let result = select4(
             network_loop(&sys_loop, &timer_service.clone(), &nvs, modem),
             async move {
                 Timer::after_millis(100).await;
-                let display = display_create(&mut display_hw)?;
+                let display = display_create(&mut display_hw, SCREEN_LAYOUT.get().orientation)?;
                 display_loop(display).await?;
                 Ok::<(), EspError>(())
             },
-            update_loop(&mut batt_sensor),
+            select(update_loop(&mut batt_sensor), setpoint_expiry_loop()),
+            storage.storage_loop(),
         )
         .await;
 
         log::error!("main loop exited {:?}", result);
+        let state = STATE_STORE.get().state.read().await.clone();
+        if let Err(e) = storage.save(&state) {
+            log::warn!("Unable to save state before power off: {e}");
+        }
         Ok::<(), EspError>(())
     })?;
 
+    // Display loop exits after the low-battery screen, e-ink keeps it without power
+    pw_display.set_low()?;
+    pw_main.set_low()?;
     drop(pw_display);
     drop(pw_main);
     Result::Ok(())
@@ -197,26 +251,44 @@ impl<'a, const SZ: usize> ButtonsHandler<'a, SZ> {
         }
         None
     }
+
+    /// Polls the button until it is released or `ms` elapses
+    fn is_held(&mut self, button: usize, ms: u32) -> bool {
+        const POLL_MS: u32 = 50;
+        for _ in 0..ms / POLL_MS {
+            if self.buttons[button].get_level() != Level::Low {
+                return false;
+            }
+            FreeRtos::delay_ms(POLL_MS);
+        }
+        true
+    }
 }
 
 fn button_thread(buttons: Buttons) -> Result<(), EspError> {
+    const LONG_PRESS_MS: u32 = 1500;
+
     let mut handler = ButtonsHandler::new([buttons.up, buttons.push, buttons.down])?;
+    let temperature = &SCREEN_LAYOUT.get().temperature;
 
     handler.enable_interrupts()?;
     loop {
         if let Some(button) = handler.wait() {
             log::info!("Button pressed {button}",);
+            let button = match button {
+                0 => Button::Up,
+                2 => Button::Down,
+                // Long press on push toggles diagnostics instead of switching pages
+                _ if handler.is_held(button, LONG_PRESS_MS) => Button::PushLong,
+                _ => Button::Push,
+            };
             let new_state = {
                 let mut w = esp_idf_svc::hal::task::block_on(async {
                     STATE_STORE.get().state.write().await
                 });
-                let adjustment = match button {
-                    0 => 0.5_f32,
-                    2 => -0.5_f32,
-                    _ => 0.0_f32,
-                };
-                w.adjust_temp_setpoint_f(adjustment);
-                w.refresh_updated_counter();
+                // Pending setpoint expires relative to the press, not the last update tick
+                w.time_since_boot = time_since_boot();
+                button.press(&mut w, temperature);
                 w.clone()
             };
             STATE_STORE.get().change_watch.sender().send(new_state);
@@ -228,6 +300,20 @@ fn button_thread(buttons: Buttons) -> Result<(), EspError> {
     // unreachable!("Button thread exited");
 }
 
+/// Time of day from the SNTP-synced system clock, `None` until the first sync
+fn local_time_of_day() -> Option<Duration> {
+    const DAY_SECS: i64 = 24 * 60 * 60;
+    // System time starts at the epoch on boot, anything before 2024 is unsynced
+    const SYNCED_AFTER_SECS: u64 = 1_704_067_200;
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+    if now.as_secs() < SYNCED_AFTER_SECS {
+        return None;
+    }
+    let local = now.as_secs() as i64 + i64::from(APP_CONFIG.utc_offset_minutes) * 60;
+    Some(Duration::from_secs(local.rem_euclid(DAY_SECS) as u64))
+}
+
 async fn update_loop(batt_sensor: &mut BatteryVoltageSensor<'_>) -> Result<(), EspError> {
     const LOOP_TICK_S: u32 = 30;
     const UPDATE_IVL_S: u32 = 15 * 60;
@@ -235,8 +321,13 @@ async fn update_loop(batt_sensor: &mut BatteryVoltageSensor<'_>) -> Result<(), E
     const UPDATE_TICKS: u32 = UPDATE_IVL_S / LOOP_TICK_S;
     const BATT_MEASURE_TICKS: usize = (60 / LOOP_TICK_S) as usize;
 
-    let loop_start = Instant::now();
-    let mut initial_soc = None;
+    // State of charge and uptime of previous boots it was measured before, restored from NVS
+    let mut initial_soc = {
+        let state = STATE_STORE.get().state.read().await;
+        state
+            .initial_state_of_charge
+            .map(|soc| (soc, state.battery_baseline_span))
+    };
     let mut loop_counter: u32 = 0;
     let mut batt_voltage_sma = SumTreeSMA::<f32, f32, BATT_MEASURE_TICKS>::new();
 
@@ -245,24 +336,44 @@ async fn update_loop(batt_sensor: &mut BatteryVoltageSensor<'_>) -> Result<(), E
     loop {
         loop_counter += 1;
 
-        let time_since_boot = Instant::now() - loop_start;
+        let time_since_boot = time_since_boot();
 
         let voltage = batt_sensor.read()?;
         batt_voltage_sma.add_sample(voltage.get::<volt>());
         let voltage_avg = Voltage::new::<volt>(batt_voltage_sma.get_average());
 
+        let baseline_settled = loop_counter >= (BATT_MEASURE_TICKS as u32) * 3;
         if loop_counter == (BATT_MEASURE_TICKS as u32) * 3 {
-            initial_soc = Some(BatteryVoltageSensor::soc(voltage_avg));
+            let soc = BatteryVoltageSensor::soc(voltage_avg);
+            // Restored baseline is dropped if the battery was charged while off
+            if !initial_soc.is_some_and(|(isoc, _)| soc <= isoc) {
+                initial_soc = Some((soc, Duration::ZERO));
+            }
         }
 
-        let soc_change_rate = initial_soc.map(|isoc| {
-            (isoc - BatteryVoltageSensor::soc(voltage_avg)) * 1000_f32 * 3600_f32
-                / (time_since_boot.as_millis() as f32)
-        });
+        let soc_change_rate = initial_soc
+            .filter(|_| baseline_settled)
+            .map(|(isoc, span)| {
+                (isoc - BatteryVoltageSensor::soc(voltage_avg)) * 1000_f32 * 3600_f32
+                    / ((span + time_since_boot).as_millis() as f32)
+            });
 
         let heap_free = unsafe { esp_idf_svc::sys::esp_get_free_heap_size() };
+        let clock = local_time_of_day();
+
+        // Averaged voltage only settles after a full measurement window
+        let battery_critical = loop_counter >= BATT_MEASURE_TICKS as u32
+            && !soc_change_rate.is_some_and(|r| r < 0.0)
+            && SCREEN_LAYOUT
+                .get()
+                .battery
+                .is_critical(voltage_avg, BatteryVoltageSensor::soc(voltage_avg));
+        if battery_critical {
+            log::warn!("Battery critical at {voltage_avg:?}, powering off");
+        }
 
-        let should_trigger_update = loop_counter == 1 || loop_counter % UPDATE_TICKS == 0;
+        let should_trigger_update =
+            loop_counter == 1 || loop_counter % UPDATE_TICKS == 0 || battery_critical;
 
         STATE_STORE
             .update_and_trigger(should_trigger_update, |writer| {
@@ -271,8 +382,13 @@ async fn update_loop(batt_sensor: &mut BatteryVoltageSensor<'_>) -> Result<(), E
                 writer.batt_voltage = voltage_avg;
                 writer.state_of_charge = BatteryVoltageSensor::soc(voltage_avg);
                 writer.state_of_charge_change_rate = soc_change_rate;
-                writer.initial_state_of_charge = initial_soc;
+                // Negative rate: state of charge went up since boot
+                writer.charging = soc_change_rate.is_some_and(|r| r < 0.0);
+                writer.battery_critical = battery_critical;
+                writer.initial_state_of_charge = initial_soc.map(|(soc, _)| soc);
+                writer.battery_baseline_span = initial_soc.map_or(Duration::ZERO, |(_, span)| span);
                 writer.free_heap_bytes = heap_free;
+                writer.clock = clock;
 
                 log::info!("Update loop state: {:?}", writer);
             })
@@ -282,3 +398,48 @@ async fn update_loop(batt_sensor: &mut BatteryVoltageSensor<'_>) -> Result<(), E
     }
     // unreachable!("update_loop exited");
 }
+
+/// Reverts a pending setpoint once its confirmation timeout passes
+async fn setpoint_expiry_loop() -> Result<(), EspError> {
+    let Some(timeout) = SCREEN_LAYOUT.get().temperature.setpoint_confirm_timeout() else {
+        return core::future::pending().await;
+    };
+    let mut watcher = STATE_STORE
+        .get()
+        .change_watch
+        .receiver()
+        .expect("Unable to allocate state watcher");
+
+    let mut state = watcher.changed().await;
+    loop {
+        let SetpointStatus::Pending { since } = state.setpoint_status else {
+            state = watcher.changed().await;
+            continue;
+        };
+
+        // Another press or a confirmation arrives as a state change and restarts the wait
+        let remaining = (since + timeout).saturating_sub(time_since_boot());
+        match select(
+            Timer::after_millis(remaining.as_millis() as u64),
+            watcher.changed(),
+        )
+        .await
+        {
+            Either::First(()) => {
+                STATE_STORE
+                    .update_if(|writer| {
+                        writer.time_since_boot = time_since_boot();
+                        let reverted = writer.expire_pending_setpoint(timeout);
+                        if reverted {
+                            log::warn!("Setpoint not confirmed in {timeout:?}, reverted");
+                        }
+                        reverted
+                    })
+                    .await;
+                // Not reverted if a press or a confirmation landed as the timer fired
+                state = STATE_STORE.get().state.read().await.clone();
+            }
+            Either::Second(changed) => state = changed,
+        }
+    }
+}
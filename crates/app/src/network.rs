@@ -1,7 +1,7 @@
 use core::str;
 use std::num::NonZero;
 
-use embassy_futures::select::{select, Either};
+use embassy_futures::select::{select, select3, Either3};
 use embassy_sync::{
     blocking_mutex::raw::{CriticalSectionRawMutex, RawMutex},
     channel::{Channel, Receiver, Sender},
@@ -11,6 +11,7 @@ use esp_idf_svc::{
     hal::modem::Modem,
     mqtt::client::{EspAsyncMqttClient, EspAsyncMqttConnection, MqttClientConfiguration, QoS},
     nvs::EspDefaultNvsPartition,
+    sntp::EspSntp,
     sys::EspError,
     timer::{EspTimerService, Task},
     wifi::{AsyncWifi, ClientConfiguration, Configuration, EspWifi},
@@ -19,14 +20,16 @@ use esp_idf_svc::{
 use esp_idf_svc::hal::{modem::WifiModemPeripheral, peripheral::Peripheral};
 
 use log::{info, warn};
-use uom::si::thermodynamic_temperature::degree_fahrenheit;
 
 use crate::{
     state_container::{StateStoreExt, STATE_STORE},
-    APP_CONFIG,
+    time_since_boot, APP_CONFIG, SCREEN_LAYOUT,
 };
-use display::state::{AppState, NetworkStatus};
-use embassy_time::Timer;
+use display::{
+    recording,
+    state::{AppState, NetworkStatus, StateReport},
+};
+use embassy_time::{Duration, Ticker, Timer};
 
 #[derive(Copy, Clone, Debug)]
 enum MqttEvent {
@@ -46,6 +49,8 @@ fn parse_f32(data: &[u8]) -> Option<f32> {
 }
 
 fn ha_mqtt_registration_payload() -> serde_json::Value {
+    let temperature = &SCREEN_LAYOUT.get().temperature;
+    let (min, max) = temperature.setpoint_range();
     serde_json::json!({
         "dev": {
             "ids": "m5paper",
@@ -58,10 +63,10 @@ fn ha_mqtt_registration_payload() -> serde_json::Value {
             "setpoint": {
                 "p": "number",
                 "device_class": "temperature",
-                "unit_of_measurement": "°F",
-                "min": 32,
-                "max": 90,
-                "step": 0.5,
+                "unit_of_measurement": temperature.unit.symbol(),
+                "min": min,
+                "max": max,
+                "step": temperature.setpoint_step,
                 "state_topic": "m5premote/setpoint/state",
                 "command_topic": "m5premote/setpoint/set",
                 "unique_id": "setpoint_temp_f",
@@ -136,18 +141,40 @@ struct MqttHandler<'ch, M: RawMutex, const N: usize, const NW: usize> {
 
 impl<'ch, M: RawMutex, const N: usize, const NW: usize> MqttHandler<'ch, M, N, NW> {
     async fn handler_loop(&mut self) {
+        let diagnostics_enabled = !APP_CONFIG.mqtt_diagnostics_topic.is_empty()
+            && APP_CONFIG.diagnostics_interval_secs > 0;
+        let mut diagnostics_ticker = Ticker::every(Duration::from_secs(
+            APP_CONFIG.diagnostics_interval_secs.max(1),
+        ));
         loop {
-            let evt = select(self.receiver.receive(), self.state_receiver.changed()).await;
+            let diagnostics_due = async {
+                if diagnostics_enabled {
+                    diagnostics_ticker.next().await
+                } else {
+                    core::future::pending().await
+                }
+            };
+            let evt = select3(
+                self.receiver.receive(),
+                self.state_receiver.changed(),
+                diagnostics_due,
+            )
+            .await;
 
             match evt {
-                Either::First(msg) => {
+                Either3::First(msg) => {
                     let r = self.handle_mqtt_evt(&msg).await;
                     log::info!("Handled {msg:?} {r:?}");
                 }
-                Either::Second(state) => {
+                Either3::Second(state) => {
                     let r = self.publish_state(&state).await;
                     log::info!("Publishied state to MQTT {r:?}")
                 }
+                Either3::Third(()) => {
+                    let state = STATE_STORE.get().state.read().await.clone();
+                    let r = self.publish_diagnostics(&state).await;
+                    log::info!("Published diagnostics {r:?}")
+                }
             }
         }
     }
@@ -182,21 +209,56 @@ impl<'ch, M: RawMutex, const N: usize, const NW: usize> MqttHandler<'ch, M, N, N
             }
             MqttEvent::Disconnected => Err(EspError::from_non_zero(NonZero::new(1).unwrap())),
             MqttEvent::ReceivedSensorData { data } => {
-                STATE_STORE.update(|s| s.set_temp_sensor_f(*data)).await;
+                let temp = SCREEN_LAYOUT
+                    .get()
+                    .temperature
+                    .sensor_unit
+                    .temperature(*data);
+                // Arrival time is stamped from the current uptime, not the last update tick
+                STATE_STORE
+                    .update(|s| {
+                        s.time_since_boot = time_since_boot();
+                        s.set_temp_sensor(temp);
+                    })
+                    .await;
                 Ok(())
             }
             MqttEvent::ReceivedSetpointData { data } => {
-                STATE_STORE.update(|s| s.set_temp_setpoint_f(*data)).await;
+                match SCREEN_LAYOUT.get().temperature.setpoint(*data) {
+                    Ok(temp) => {
+                        STATE_STORE
+                            .update(|s| {
+                                s.time_since_boot = time_since_boot();
+                                s.set_temp_setpoint(temp);
+                            })
+                            .await
+                    }
+                    Err(e) => warn!("Rejected setpoint command: {e}"),
+                }
                 Ok(())
             }
         }
     }
 
+    async fn publish_diagnostics(&mut self, state: &AppState) -> Result<(), EspError> {
+        let payload = serde_json::to_vec(&StateReport::new(state)).expect("State is serializable");
+        self.client
+            .publish(
+                APP_CONFIG.mqtt_diagnostics_topic,
+                QoS::AtMostOnce,
+                true,
+                &payload,
+            )
+            .await?;
+        Ok(())
+    }
+
     async fn publish_state(&mut self, state: &AppState) -> Result<(), EspError> {
         let setpoint = state.temp_setpoint;
         info!("Publishing setpoint {setpoint:?} to state topic");
         if let Some(setpoint) = setpoint {
-            let setpoint_str = format!("{:.1}", setpoint.get::<degree_fahrenheit>());
+            let unit = SCREEN_LAYOUT.get().temperature.unit;
+            let setpoint_str = format!("{:.1}", unit.value(setpoint));
             self.client
                 .publish(
                     "m5premote/setpoint/state",
@@ -207,6 +269,17 @@ impl<'ch, M: RawMutex, const N: usize, const NW: usize> MqttHandler<'ch, M, N, N
                 .await?;
         }
 
+        if !APP_CONFIG.mqtt_record_topic.is_empty() {
+            self.client
+                .publish(
+                    APP_CONFIG.mqtt_record_topic,
+                    QoS::AtMostOnce,
+                    false,
+                    recording::encode(state).as_bytes(),
+                )
+                .await?;
+        }
+
         Ok(())
     }
 }
@@ -274,6 +347,8 @@ pub async fn network_loop(
 ) -> Result<(), EspError> {
     let mut esp_wifi = wifi_create(sys_loop, nvs, modem).await?;
     let mut wifi = AsyncWifi::wrap(&mut esp_wifi, sys_loop.clone(), timer_service.clone())?;
+    // Keeps the system clock synced whenever wifi is up, for the idle screen clock
+    let _sntp = EspSntp::new_default()?;
 
     let _ = select(
         async move {
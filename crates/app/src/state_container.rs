@@ -24,20 +24,32 @@ pub trait StateStoreExt {
 
     async fn update_and_trigger<U>(&self, trigger_update: bool, f: U)
     where
-        U: FnOnce(&mut AppState);
+        U: FnOnce(&mut AppState),
+    {
+        self.update_if(|s| {
+            f(s);
+            trigger_update
+        })
+        .await;
+    }
+
+    /// Triggers an update when `f` returns true
+    async fn update_if<U>(&self, f: U)
+    where
+        U: FnOnce(&mut AppState) -> bool;
 }
 
 impl StateStoreExt for LazyLock<StateStore> {
-    async fn update_and_trigger<U>(&self, trigger_update: bool, f: U)
+    async fn update_if<U>(&self, f: U)
     where
-        U: FnOnce(&mut AppState),
+        U: FnOnce(&mut AppState) -> bool,
     {
         let state_store = self.get();
-        let new_state = {
+        let (trigger_update, new_state) = {
             let mut writer = state_store.state.write().await;
-            f(&mut writer);
+            let trigger_update = f(&mut writer);
             writer.refresh_updated_counter();
-            writer.clone()
+            (trigger_update, writer.clone())
         };
         if trigger_update {
             state_store.change_watch.sender().send(new_state);
@@ -0,0 +1,109 @@
+use std::time::{Duration, Instant};
+
+use esp_idf_svc::{
+    nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault},
+    sys::EspError,
+};
+use log::{info, warn};
+
+use display::{persist::Persisted, state::AppState};
+
+use crate::{
+    state_container::{StateStoreExt, STATE_STORE},
+    SCREEN_LAYOUT,
+};
+
+const NAMESPACE: &str = "app_state";
+const KEY: &str = "state";
+/// Fits the full temperature history
+const MAX_SIZE: usize = 2048;
+/// History and battery baseline change with every update, they are saved at most this often
+const SAVE_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// State kept across reboots in NVS, see `display::persist` for the format
+pub struct StateStorage {
+    nvs: EspNvs<NvsDefault>,
+    saved: Option<Persisted>,
+    saved_at: Instant,
+}
+
+impl StateStorage {
+    pub fn new(partition: EspDefaultNvsPartition) -> Result<StateStorage, EspError> {
+        Ok(StateStorage {
+            nvs: EspNvs::new(partition, NAMESPACE, true)?,
+            saved: None,
+            saved_at: Instant::now(),
+        })
+    }
+
+    /// Applies the saved state to the store, called at boot before anything is drawn
+    pub async fn restore(&mut self) {
+        let mut buf = vec![0; MAX_SIZE];
+        let persisted = match self.nvs.get_blob(KEY, &mut buf) {
+            Ok(Some(bytes)) => Persisted::decode(bytes),
+            Ok(None) => {
+                info!("No saved state");
+                return;
+            }
+            Err(e) => {
+                warn!("Unable to read saved state: {e}");
+                return;
+            }
+        };
+
+        match persisted {
+            Ok(persisted) => {
+                let temperature = &SCREEN_LAYOUT.get().temperature;
+                if persisted.unit != temperature.unit {
+                    info!(
+                        "State saved in {:?}, setpoint is fitted to {:?}",
+                        persisted.unit, temperature.unit
+                    );
+                }
+                STATE_STORE
+                    .update_and_trigger(false, |s| persisted.apply(s, temperature))
+                    .await;
+                info!("Restored saved state {persisted:?}");
+                self.saved = Some(persisted);
+            }
+            Err(e) => warn!("Discarding saved state: {e}"),
+        }
+    }
+
+    pub fn save(&mut self, state: &AppState) -> Result<(), EspError> {
+        let persisted = Persisted::from_state(state, SCREEN_LAYOUT.get().temperature.unit);
+        self.nvs.set_blob(KEY, &persisted.encode())?;
+        self.saved = Some(persisted);
+        self.saved_at = Instant::now();
+        Ok(())
+    }
+
+    /// Setpoint, unit and page changes are saved right away, the rest on the interval
+    fn is_due(&self, state: &AppState) -> bool {
+        let Some(saved) = &self.saved else {
+            return true;
+        };
+        let current = Persisted::from_state(state, SCREEN_LAYOUT.get().temperature.unit);
+        saved.setpoint != current.setpoint
+            || saved.unit != current.unit
+            || saved.active_page != current.active_page
+            || self.saved_at.elapsed() >= SAVE_INTERVAL
+    }
+
+    pub async fn storage_loop(&mut self) -> Result<(), EspError> {
+        let mut watcher = STATE_STORE
+            .get()
+            .change_watch
+            .receiver()
+            .expect("Unable to allocate state watcher");
+        loop {
+            let state = watcher.changed().await;
+            if self.is_due(&state) {
+                match self.save(&state) {
+                    Ok(()) => info!("State saved"),
+                    Err(e) => warn!("Unable to save state: {e}"),
+                }
+            }
+        }
+    }
+}
@@ -1,3 +1,7 @@
+use std::time::{Duration, Instant};
+
+use embassy_futures::select::select;
+use embassy_time::Timer;
 use embedded_graphics::pixelcolor::Gray4;
 use esp_idf_svc::sys::EspError;
 
@@ -5,9 +9,17 @@ use embedded_graphics::prelude::*;
 use it8951::*;
 use log::info;
 
-use display::renderer::{DrawResult, Renderer};
+use display::{
+    recording,
+    refresh::Waveform,
+    renderer::{DrawResult, Renderer},
+};
 
-use crate::{hardware::M5Display, state_container::STATE_STORE};
+use crate::{
+    hardware::M5Display,
+    state_container::{StateStoreExt, STATE_STORE},
+    APP_CONFIG, SCREEN_LAYOUT,
+};
 
 pub async fn display_loop(display: M5Display<'_>) -> Result<(), EspError> {
     let state = STATE_STORE.get();
@@ -17,35 +29,56 @@ pub async fn display_loop(display: M5Display<'_>) -> Result<(), EspError> {
     display.clear(Gray4::WHITE).expect("clear");
     display.display(WaveformMode::Init).expect("display update");
 
-    let mut renderer = Renderer::new(&display.bounding_box());
+    let mut renderer = Renderer::new(&display.bounding_box(), SCREEN_LAYOUT.get());
     let mut watcher = state
         .change_watch
         .receiver()
         .expect("Unable to allocate state watcher");
+    let idle_timeout = Duration::from_secs(SCREEN_LAYOUT.get().idle.timeout_secs);
+    // Button press count and when it last changed
+    let mut last_input = (0, Instant::now());
     loop {
         let app_state = { state.state.read().await.clone() };
+        if app_state.button_presses != last_input.0 {
+            last_input = (app_state.button_presses, Instant::now());
+        }
+        let idle_at =
+            (!idle_timeout.is_zero() && !app_state.idle).then(|| last_input.1 + idle_timeout);
+        if idle_at.is_some_and(|at| at <= Instant::now()) {
+            info!("No input for {idle_timeout:?}, entering idle mode");
+            STATE_STORE.update(|s| s.idle = true).await;
+            continue;
+        }
+
+        if APP_CONFIG.record_state {
+            info!("{}", recording::encode(&app_state));
+        }
         let result = renderer.draw(&app_state, &mut display).expect("Draw error");
         let last_updated_counter = app_state.updated_counter;
 
-        let (area_to_refresh, sleep) = match result {
-            DrawResult::Partial(bb) => {
-                log::info!("Partial draw {bb:?}");
-                (Some(bb), false)
+        let (regions_to_refresh, sleep) = match result {
+            DrawResult::Partial(regions) => {
+                log::info!("Partial draw {regions:?}");
+                (regions, false)
+            }
+            DrawResult::Full(region) => {
+                log::info!("Full draw {region:?}");
+                (vec![region], true)
             }
-            DrawResult::Complete(bb) => {
-                log::info!("Complete draw {bb:?}");
-                (Some(bb), true)
+            DrawResult::Complete(regions) => {
+                log::info!("Complete draw {regions:?}");
+                (regions, true)
             }
             DrawResult::None => {
                 log::info!("No screen updates");
-                (None, true)
+                (Vec::new(), true)
             }
         };
 
-        if let Some(bb) = area_to_refresh {
-            let bb = display_bb.intersection(&bb);
+        for region in regions_to_refresh {
+            let bb = display_bb.intersection(&region.area);
 
-            info!("Refreshing {bb:?}");
+            info!("Refreshing {bb:?} with {:?}", region.waveform);
             display
                 .display_area(
                     &AreaImgInfo {
@@ -54,16 +87,39 @@ pub async fn display_loop(display: M5Display<'_>) -> Result<(), EspError> {
                         area_w: bb.size.width as u16,
                         area_h: bb.size.height as u16,
                     },
-                    WaveformMode::A2, // WaveformMode::GLR16,
+                    waveform_mode(region.waveform),
                 )
                 .expect("display update");
         }
 
+        if app_state.battery_critical {
+            let _display = display.sleep().expect("sleep");
+            info!("Low-battery screen shown, display loop exits");
+            return Ok(());
+        }
+
         if sleep && !state.state.read().await.is_new(last_updated_counter) {
             display = {
                 let display = display.sleep().expect("sleep");
                 info!("Screen powered down, awaiting change");
-                watcher.changed().await;
+                // Wakes up early for idle mode or the clean refresh, whichever comes first
+                let wake_at = match (idle_at, renderer.clean_refresh_at()) {
+                    (Some(idle), Some(clean)) => Some(idle.min(clean)),
+                    (idle, clean) => idle.or(clean),
+                };
+                match wake_at {
+                    Some(at) => {
+                        let timeout = at.saturating_duration_since(Instant::now());
+                        select(
+                            watcher.changed(),
+                            Timer::after_millis(timeout.as_millis() as u64),
+                        )
+                        .await;
+                    }
+                    None => {
+                        watcher.changed().await;
+                    }
+                }
                 display.sys_run().expect("wake")
             };
             info!("Display awakened");
@@ -73,3 +129,11 @@ pub async fn display_loop(display: M5Display<'_>) -> Result<(), EspError> {
     }
     // unreachable!("display_loop exited");
 }
+
+fn waveform_mode(waveform: Waveform) -> WaveformMode {
+    match waveform {
+        Waveform::A2 => WaveformMode::A2,
+        Waveform::Gl16 => WaveformMode::GL16,
+        Waveform::Gc16 => WaveformMode::GrayscaleClearing16,
+    }
+}
@@ -1,17 +1,32 @@
 use std::{convert::Infallible, time::Duration};
 
 use display::{
+    layout::{Orientation, ScreenLayout},
     renderer::{DrawResult, Error, Renderer},
     state::{AppState, Voltage},
 };
 use embedded_graphics::{pixelcolor::Gray4, prelude::*};
 use embedded_graphics_simulator::{OutputSettingsBuilder, SimulatorDisplay, Window};
-use uom::si::electric_potential::volt;
+use uom::si::{
+    electric_potential::volt, f32::ThermodynamicTemperature,
+    thermodynamic_temperature::degree_fahrenheit,
+};
 
 fn main() -> Result<(), Error<Infallible>> {
-    let mut display = SimulatorDisplay::<Gray4>::new(Size::new(540, 960));
+    let mut args = std::env::args().skip(1);
+    let layout_path = args.next().unwrap_or_else(|| "layout.toml".to_owned());
+    let page = args.next().and_then(|p| p.parse().ok()).unwrap_or(0);
+    let mut layout = ScreenLayout::from_file(&layout_path).expect("Unable to load layout");
+    match args.next().as_deref() {
+        Some("portrait") => layout.orientation = Orientation::Portrait,
+        Some("landscape") => layout.orientation = Orientation::Landscape,
+        Some(o) => panic!("Unknown orientation {o}, expected portrait or landscape"),
+        None => {}
+    }
+
+    let mut display = SimulatorDisplay::<Gray4>::new(layout.orientation.size());
 
-    let mut renderer = Renderer::new(&display.bounding_box());
+    let mut renderer = Renderer::new(&display.bounding_box(), &layout);
 
     let mut state = AppState {
         updated_counter: 2460,
@@ -23,12 +38,24 @@ fn main() -> Result<(), Error<Infallible>> {
         state_of_charge_change_rate: Some(0.0088),
         network_status: display::state::NetworkStatus::MqttConnected,
         free_heap_bytes: 189000,
+        active_page: page,
+        button_presses: 0,
         temp_sensor: None,
         temp_setpoint: None,
+        ..AppState::new()
     };
 
-    state.set_temp_sensor_f(73.2_f32);
-    state.set_temp_setpoint_f(72.5_f32);
+    // Synthetic day of readings for the history graph
+    for i in (1..120_u32).rev() {
+        let temp = 73.2 + 2.0 * (i as f32 / 12.0).sin();
+        state.temp_history.push(
+            state.time_since_boot - Duration::from_secs(u64::from(i) * 600),
+            ThermodynamicTemperature::new::<degree_fahrenheit>(temp),
+        );
+    }
+
+    state.set_temp_sensor(ThermodynamicTemperature::new::<degree_fahrenheit>(73.2_f32));
+    state.set_temp_setpoint(ThermodynamicTemperature::new::<degree_fahrenheit>(72.5_f32));
 
     display.clear(Gray4::WHITE)?;
 
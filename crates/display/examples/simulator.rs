@@ -0,0 +1,244 @@
+//! Interactive simulator: keyboard stands in for the M5Paper buttons and the
+//! state ticks once a second, screen is redrawn incrementally by `Renderer::draw`.
+//!
+//! Up / Down  - setpoint up / down
+//! Enter      - next page, Shift+Enter is a long press (toggles diagnostics)
+//! I          - enter idle mode, the next button press leaves it
+//! C          - confirm the setpoint, as if echoed on the command topic
+//! S          - pause / resume the sensor, paused values go stale
+//! R          - outline the regions of the last refresh
+//! Esc        - quit
+//!
+//! `simulator [layout.toml] [--replay <log> [--speed <factor>]]` plays back the
+//! `@state` records of a firmware log instead, 60 times faster by default.
+
+use std::{
+    convert::Infallible,
+    thread,
+    time::{Duration, Instant},
+};
+
+use display::{
+    buttons::Button,
+    layout::ScreenLayout,
+    recording,
+    refresh::{DirtyRegion, Waveform},
+    renderer::{DrawResult, Error, Renderer},
+    state::{AppState, NetworkStatus, Voltage},
+};
+use embedded_graphics::{pixelcolor::Gray4, prelude::*, primitives::PrimitiveStyle};
+use embedded_graphics_simulator::{
+    sdl2::{Keycode, Mod},
+    OutputSettingsBuilder, SimulatorDisplay, SimulatorEvent, Window,
+};
+use uom::si::{
+    electric_potential::volt, f32::ThermodynamicTemperature,
+    thermodynamic_temperature::degree_fahrenheit,
+};
+
+const TICK: Duration = Duration::from_secs(1);
+
+fn main() -> Result<(), Error<Infallible>> {
+    let mut layout_path = "layout.toml".to_owned();
+    let mut replay_path = None;
+    let mut speed = 60.0;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--replay" => replay_path = Some(args.next().expect("--replay needs a log file")),
+            "--speed" => {
+                speed = args
+                    .next()
+                    .and_then(|s| s.parse().ok())
+                    .expect("--speed needs a number")
+            }
+            _ => layout_path = arg,
+        }
+    }
+    let layout = ScreenLayout::from_file(&layout_path).expect("Unable to load layout");
+    let mut replay = replay_path.map(|path| Replay::load(&path, speed));
+
+    let mut display = SimulatorDisplay::<Gray4>::new(layout.orientation.size());
+    let mut renderer = Renderer::new(&display.bounding_box(), &layout);
+    let mut state = AppState {
+        batt_voltage: Voltage::new::<volt>(4.0141_f32),
+        state_of_charge: 0.79,
+        initial_state_of_charge: Some(0.98),
+        network_status: NetworkStatus::MqttConnected,
+        free_heap_bytes: 189000,
+        ..AppState::new()
+    };
+    state.set_temp_setpoint(ThermodynamicTemperature::new::<degree_fahrenheit>(72.5_f32));
+
+    let output_settings = OutputSettingsBuilder::new()
+        .pixel_spacing(0)
+        .scale(1)
+        .build();
+    let mut window = Window::new("Display simulator", &output_settings);
+
+    let mut show_regions = replay.is_some();
+    let mut sensor_paused = false;
+    let mut last_regions = Vec::new();
+    let mut next_tick = Instant::now();
+    loop {
+        if let Some(replay) = &mut replay {
+            if let Some(next) = replay.due() {
+                state = next;
+            }
+        } else if Instant::now() >= next_tick {
+            next_tick += TICK;
+            tick(&mut state, &layout, sensor_paused);
+        }
+
+        let regions = match renderer.draw(&state, &mut display)? {
+            DrawResult::Full(region) => vec![region],
+            DrawResult::Complete(regions) | DrawResult::Partial(regions) => regions,
+            DrawResult::None => Vec::new(),
+        };
+        if !regions.is_empty() {
+            for r in &regions {
+                log::info!("Refresh {:?} with {:?}", r.area, r.waveform);
+            }
+            last_regions = regions;
+        }
+
+        if show_regions {
+            window.update(&with_regions(&display, &last_regions)?);
+        } else {
+            window.update(&display);
+        }
+
+        for event in window.events() {
+            let button = match event {
+                SimulatorEvent::Quit => return Ok(()),
+                SimulatorEvent::KeyDown {
+                    keycode, keymod, ..
+                } => match keycode {
+                    Keycode::ESCAPE => return Ok(()),
+                    Keycode::R => {
+                        show_regions = !show_regions;
+                        None
+                    }
+                    Keycode::I => {
+                        state.idle = true;
+                        None
+                    }
+                    Keycode::S => {
+                        sensor_paused = !sensor_paused;
+                        log::info!("Sensor paused: {sensor_paused}");
+                        None
+                    }
+                    Keycode::C => {
+                        if let Some(setpoint) = state.temp_setpoint {
+                            state.set_temp_setpoint(setpoint);
+                            state.refresh_updated_counter();
+                        }
+                        None
+                    }
+                    Keycode::UP => Some(Button::Up),
+                    Keycode::DOWN => Some(Button::Down),
+                    Keycode::RETURN if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) => {
+                        Some(Button::PushLong)
+                    }
+                    Keycode::RETURN => Some(Button::Push),
+                    _ => None,
+                },
+                _ => None,
+            };
+            if let Some(button) = button {
+                log::info!("Button pressed {button:?}");
+                button.press(&mut state, &layout.temperature);
+            }
+        }
+
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// States rebuilt from a log, handed out as their recorded uptime comes due
+struct Replay {
+    states: std::iter::Peekable<std::vec::IntoIter<AppState>>,
+    /// Uptime of the first record, played at `start`
+    first: Duration,
+    start: Instant,
+    speed: f64,
+    step: usize,
+}
+
+impl Replay {
+    fn load(path: &str, speed: f64) -> Replay {
+        let log = std::fs::read_to_string(path).expect("Unable to read replay log");
+        let mut state = AppState::new();
+        let mut states = Vec::new();
+        for line in log.lines().filter(|l| l.contains(recording::RECORD_TAG)) {
+            match recording::apply(line, &mut state) {
+                Ok(()) => states.push(state.clone()),
+                Err(e) => log::warn!("Skipping record: {e}"),
+            }
+        }
+        log::info!("Replaying {} states from {path}", states.len());
+
+        Replay {
+            first: states.first().map_or(Duration::ZERO, |s| s.time_since_boot),
+            states: states.into_iter().peekable(),
+            start: Instant::now(),
+            speed: speed.max(f64::MIN_POSITIVE),
+            step: 0,
+        }
+    }
+
+    fn due(&mut self) -> Option<AppState> {
+        let due = self
+            .states
+            .peek()?
+            .time_since_boot
+            .saturating_sub(self.first);
+        if due.div_f64(self.speed) > self.start.elapsed() {
+            return None;
+        }
+
+        self.step += 1;
+        let state = self.states.next()?;
+        log::info!("Step {} at {:?}", self.step, state.time_since_boot);
+        Some(state)
+    }
+}
+
+/// What the update loop would change in a second: uptime, clock, counter, a drifting
+/// sensor and the expiry of an unconfirmed setpoint
+fn tick(state: &mut AppState, layout: &ScreenLayout, sensor_paused: bool) {
+    state.time_since_boot += TICK;
+    state.clock = Some(Duration::from_secs(7 * 3600) + state.time_since_boot);
+    state.loop_counter += 1;
+    if !sensor_paused {
+        let minutes = state.time_since_boot.as_secs_f32() / 60.0;
+        state.set_temp_sensor(ThermodynamicTemperature::new::<degree_fahrenheit>(
+            73.0 + 2.0 * minutes.sin(),
+        ));
+    }
+    if let Some(timeout) = layout.temperature.setpoint_confirm_timeout() {
+        if state.expire_pending_setpoint(timeout) {
+            log::info!("Setpoint not confirmed in {timeout:?}, reverted");
+        }
+    }
+    state.refresh_updated_counter();
+}
+
+/// Copy of the screen with refreshed regions outlined, darker for faster waveforms
+fn with_regions(
+    display: &SimulatorDisplay<Gray4>,
+    regions: &[DirtyRegion],
+) -> Result<SimulatorDisplay<Gray4>, Infallible> {
+    let mut shown = display.clone();
+    for r in regions {
+        let color = match r.waveform {
+            Waveform::A2 => Gray4::new(0x4),
+            Waveform::Gl16 => Gray4::new(0x8),
+            Waveform::Gc16 => Gray4::new(0xb),
+        };
+        r.area
+            .into_styled(PrimitiveStyle::with_stroke(color, 2))
+            .draw(&mut shown)?;
+    }
+    Ok(shown)
+}
@@ -0,0 +1,31 @@
+use crate::state::AppState;
+use crate::units::TemperatureSettings;
+
+/// Presses of the three M5Paper buttons. Firmware reads them from GPIO,
+/// the simulator from the keyboard; both apply them through `press`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Up,
+    Down,
+    Push,
+    /// Push held for more than a second and a half
+    PushLong,
+}
+
+impl Button {
+    /// First press while idle only wakes the screen up
+    pub fn press(self, state: &mut AppState, temperature: &TemperatureSettings) {
+        state.button_presses = state.button_presses.wrapping_add(1);
+        if state.idle {
+            state.idle = false;
+        } else {
+            match self {
+                Button::Up => state.adjust_temp_setpoint(1, temperature),
+                Button::Down => state.adjust_temp_setpoint(-1, temperature),
+                Button::Push => state.next_page(),
+                Button::PushLong => state.toggle_diagnostics(),
+            }
+        }
+        state.refresh_updated_counter();
+    }
+}
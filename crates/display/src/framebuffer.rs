@@ -0,0 +1,144 @@
+use std::convert::Infallible;
+
+use embedded_graphics::{
+    pixelcolor::{Gray4, GrayColor},
+    prelude::{DrawTarget, OriginDimensions, Pixel, Point, Size},
+};
+
+/// In-memory `Gray4` draw target, renders layouts without a panel or a window.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Framebuffer {
+    size: Size,
+    pixels: Vec<u8>,
+}
+
+impl Framebuffer {
+    /// Blank white framebuffer, same as a freshly cleared panel
+    pub fn new(size: Size) -> Framebuffer {
+        Framebuffer {
+            size,
+            pixels: vec![Gray4::WHITE.luma(); (size.width * size.height) as usize],
+        }
+    }
+
+    pub fn pixel(&self, p: Point) -> Option<Gray4> {
+        self.index(p).map(|idx| Gray4::new(self.pixels[idx]))
+    }
+
+    /// Number of pixels that differ, `None` if sizes do not match
+    pub fn diff(&self, other: &Framebuffer) -> Option<usize> {
+        if self.size != other.size {
+            return None;
+        }
+        Some(
+            self.pixels
+                .iter()
+                .zip(other.pixels.iter())
+                .filter(|(a, b)| a != b)
+                .count(),
+        )
+    }
+
+    /// Binary PGM with maxval 15, one byte per pixel
+    pub fn to_pgm(&self) -> Vec<u8> {
+        let mut pgm = format!("P5\n{} {}\n15\n", self.size.width, self.size.height).into_bytes();
+        pgm.extend_from_slice(&self.pixels);
+        pgm
+    }
+
+    pub fn from_pgm(data: &[u8]) -> Option<Framebuffer> {
+        // Header is four whitespace separated tokens followed by a single whitespace
+        let mut tokens = Vec::with_capacity(4);
+        let mut pos = 0;
+        while tokens.len() < 4 {
+            while data.get(pos)?.is_ascii_whitespace() {
+                pos += 1;
+            }
+            let start = pos;
+            while !data.get(pos)?.is_ascii_whitespace() {
+                pos += 1;
+            }
+            tokens.push(std::str::from_utf8(&data[start..pos]).ok()?);
+        }
+        let pixels = data.get(pos + 1..)?;
+
+        let width: u32 = tokens[1].parse().ok()?;
+        let height: u32 = tokens[2].parse().ok()?;
+        if tokens[0] != "P5"
+            || tokens[3] != "15"
+            || pixels.len() != (width * height) as usize
+            || pixels.iter().any(|p| *p > 15)
+        {
+            return None;
+        }
+
+        Some(Framebuffer {
+            size: Size::new(width, height),
+            pixels: pixels.to_vec(),
+        })
+    }
+
+    #[cfg(feature = "png")]
+    pub fn to_png(&self) -> Result<Vec<u8>, png::EncodingError> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, self.size.width, self.size.height);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        // Scale 0..15 to 0..255
+        let pixels: Vec<u8> = self.pixels.iter().map(|p| p * 17).collect();
+        encoder.write_header()?.write_image_data(&pixels)?;
+        Ok(png)
+    }
+
+    /// Reads back what `to_png` writes, other gray levels are rejected
+    #[cfg(feature = "png")]
+    pub fn from_png(data: &[u8]) -> Option<Framebuffer> {
+        let mut reader = png::Decoder::new(std::io::Cursor::new(data))
+            .read_info()
+            .ok()?;
+        let mut pixels = vec![0; reader.output_buffer_size()?];
+        let info = reader.next_frame(&mut pixels).ok()?;
+        if info.color_type != png::ColorType::Grayscale
+            || info.bit_depth != png::BitDepth::Eight
+            || pixels.iter().any(|p| p % 17 != 0)
+        {
+            return None;
+        }
+
+        Some(Framebuffer {
+            size: Size::new(info.width, info.height),
+            pixels: pixels.iter().map(|p| p / 17).collect(),
+        })
+    }
+
+    fn index(&self, p: Point) -> Option<usize> {
+        if p.x < 0 || p.y < 0 || p.x as u32 >= self.size.width || p.y as u32 >= self.size.height {
+            return None;
+        }
+        Some(p.y as usize * self.size.width as usize + p.x as usize)
+    }
+}
+
+impl OriginDimensions for Framebuffer {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl DrawTarget for Framebuffer {
+    type Color = Gray4;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(p, color) in pixels {
+            if let Some(idx) = self.index(p) {
+                self.pixels[idx] = color.luma();
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+
+use embedded_graphics::{prelude::*, primitives::Rectangle};
+use serde::Deserialize;
+
+use crate::layout::Position;
+
+/// Splits the screen into equal columns, rows are as tall as their tallest widget.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct GridLayout {
+    pub columns: u32,
+    /// Space between the screen edge and the grid
+    pub padding: u32,
+    pub column_gap: u32,
+    pub row_gap: u32,
+}
+
+impl Default for GridLayout {
+    fn default() -> Self {
+        GridLayout {
+            columns: 2,
+            padding: 16,
+            column_gap: 16,
+            row_gap: 40,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CellAlign {
+    Start,
+    Center,
+    End,
+}
+
+impl CellAlign {
+    /// Offset of an item of `size` inside `space`, items larger than the space start at 0
+    fn offset(&self, space: u32, size: u32) -> i32 {
+        let free = space.saturating_sub(size) as i32;
+        match self {
+            CellAlign::Start => 0,
+            CellAlign::Center => free / 2,
+            CellAlign::End => free,
+        }
+    }
+}
+
+/// Where a widget goes: a row, a run of `span` columns and its alignment inside them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub row: u32,
+    pub column: u32,
+    pub span: u32,
+    pub halign: CellAlign,
+    pub valign: CellAlign,
+}
+
+impl From<Position> for Cell {
+    fn from(position: Position) -> Self {
+        Cell {
+            row: position.row,
+            column: position.column,
+            span: 1,
+            halign: CellAlign::Center,
+            valign: CellAlign::Start,
+        }
+    }
+}
+
+impl GridLayout {
+    /// Top-left corner for each `(cell, size)` item and the area the grid takes.
+    /// Rows without items take no space, `valign` places the grid inside `area`.
+    pub(crate) fn arrange(
+        &self,
+        area: &Rectangle,
+        items: &[(Cell, Size)],
+        valign: CellAlign,
+    ) -> (Vec<Point>, Rectangle) {
+        let columns = self.columns.max(1);
+        let inner = Rectangle::new(
+            area.top_left + Point::new(self.padding as i32, self.padding as i32),
+            Size::new(
+                area.size.width.saturating_sub(2 * self.padding),
+                area.size.height.saturating_sub(2 * self.padding),
+            ),
+        );
+        let column_width = inner
+            .size
+            .width
+            .saturating_sub((columns - 1) * self.column_gap)
+            / columns;
+
+        let mut row_heights: BTreeMap<u32, u32> = BTreeMap::new();
+        for (cell, size) in items {
+            let h = row_heights.entry(cell.row).or_default();
+            *h = (*h).max(size.height);
+        }
+
+        let height = row_heights.values().sum::<u32>()
+            + row_heights.len().saturating_sub(1) as u32 * self.row_gap;
+        let mut y = inner.top_left.y + valign.offset(inner.size.height, height);
+        let grid = Rectangle::new(
+            Point::new(inner.top_left.x, y),
+            Size::new(inner.size.width, height),
+        );
+
+        let mut row_tops = BTreeMap::new();
+        for (row, h) in &row_heights {
+            row_tops.insert(*row, y);
+            y += (h + self.row_gap) as i32;
+        }
+
+        let positions = items
+            .iter()
+            .map(|(cell, size)| {
+                let column = cell.column.min(columns - 1);
+                let span = cell.span.clamp(1, columns - column);
+                let width = span * column_width + (span - 1) * self.column_gap;
+                let x = inner.top_left.x + (column * (column_width + self.column_gap)) as i32;
+                Point::new(
+                    x + cell.halign.offset(width, size.width),
+                    row_tops[&cell.row] + cell.valign.offset(row_heights[&cell.row], size.height),
+                )
+            })
+            .collect();
+
+        (positions, grid)
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use uom::si::{f32::ThermodynamicTemperature, thermodynamic_temperature::kelvin};
+
+/// Ring buffer of timestamped readings, downsampled into fixed time buckets.
+/// Readings that fall into the same bucket are averaged.
+#[derive(Clone, Debug)]
+pub struct History {
+    bucket: Duration,
+    capacity: usize,
+    /// Span of readings restored from previous boots, boot is at this time
+    origin: Duration,
+    samples: VecDeque<HistorySample>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HistorySample {
+    /// Latest reading in the bucket, time since boot plus the restored span
+    pub at: Duration,
+    pub value: ThermodynamicTemperature,
+    count: u32,
+}
+
+impl History {
+    pub fn new(bucket: Duration, capacity: usize) -> History {
+        History {
+            bucket,
+            capacity,
+            origin: Duration::ZERO,
+            samples: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Replaces the readings with ones from a previous boot, given as their
+    /// age at boot, oldest first. Time the device was off isn't counted.
+    pub fn restore(
+        &mut self,
+        readings: impl IntoIterator<Item = (Duration, ThermodynamicTemperature)>,
+    ) {
+        let readings: Vec<_> = readings.into_iter().collect();
+        let skip = readings.len().saturating_sub(self.capacity);
+        self.origin = readings.iter().map(|r| r.0).max().unwrap_or_default();
+        self.samples = readings
+            .into_iter()
+            .skip(skip)
+            .map(|(age, value)| HistorySample {
+                at: self.origin.saturating_sub(age),
+                value,
+                count: 1,
+            })
+            .collect();
+    }
+
+    /// Age of each bucket's latest reading at `now` (time since boot), oldest first
+    pub fn ages(
+        &self,
+        now: Duration,
+    ) -> impl Iterator<Item = (Duration, ThermodynamicTemperature)> + '_ {
+        let now = now + self.origin;
+        self.samples
+            .iter()
+            .map(move |s| (now.saturating_sub(s.at), s.value))
+    }
+
+    /// Span of time covered by a full buffer
+    pub fn window(&self) -> Duration {
+        self.bucket * self.capacity as u32
+    }
+
+    pub fn samples(&self) -> impl Iterator<Item = &HistorySample> {
+        self.samples.iter()
+    }
+
+    /// `at` is the time since boot of the reading
+    pub fn push(&mut self, at: Duration, value: ThermodynamicTemperature) {
+        let at = at + self.origin;
+        let bucket_of = |t: Duration| t.as_millis() / self.bucket.as_millis().max(1);
+
+        if let Some(last) = self.samples.back_mut() {
+            if bucket_of(last.at) == bucket_of(at) {
+                let n = last.count as f32;
+                let avg = (last.value.get::<kelvin>() * n + value.get::<kelvin>()) / (n + 1.0);
+                last.value = ThermodynamicTemperature::new::<kelvin>(avg);
+                last.at = at;
+                last.count += 1;
+                return;
+            }
+        }
+
+        self.samples.push_back(HistorySample {
+            at,
+            value,
+            count: 1,
+        });
+
+        let window = self.window();
+        while self.samples.len() > self.capacity
+            || self
+                .samples
+                .front()
+                .is_some_and(|s| at.saturating_sub(s.at) > window)
+        {
+            self.samples.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uom::si::thermodynamic_temperature::degree_fahrenheit;
+
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn push_f(history: &mut History, minutes: u32, value: f32) {
+        history.push(
+            MINUTE * minutes,
+            ThermodynamicTemperature::new::<degree_fahrenheit>(value),
+        );
+    }
+
+    fn values_f(history: &History) -> Vec<f32> {
+        history
+            .samples()
+            .map(|s| (s.value.get::<degree_fahrenheit>() * 10.0).round() / 10.0)
+            .collect()
+    }
+
+    #[test]
+    fn readings_in_one_bucket_averaged() {
+        let mut history = History::new(MINUTE * 10, 6);
+        push_f(&mut history, 0, 70.0);
+        push_f(&mut history, 4, 71.0);
+        push_f(&mut history, 9, 72.0);
+        push_f(&mut history, 10, 75.0);
+
+        assert_eq!(values_f(&history), [71.0, 75.0]);
+        let at: Vec<_> = history.samples().map(|s| s.at).collect();
+        assert_eq!(at, [MINUTE * 9, MINUTE * 10]);
+    }
+
+    #[test]
+    fn capacity_keeps_newest_buckets() {
+        let mut history = History::new(MINUTE, 3);
+        for minute in 0..5 {
+            push_f(&mut history, minute, 70.0 + minute as f32);
+        }
+        assert_eq!(values_f(&history), [72.0, 73.0, 74.0]);
+    }
+
+    #[test]
+    fn readings_older_than_window_evicted() {
+        let mut history = History::new(MINUTE * 10, 6);
+        push_f(&mut history, 0, 70.0);
+        push_f(&mut history, 30, 71.0);
+        // Gap longer than the hour window leaves only the new reading
+        push_f(&mut history, 95, 72.0);
+
+        assert_eq!(history.window(), MINUTE * 60);
+        assert_eq!(values_f(&history), [72.0]);
+    }
+}
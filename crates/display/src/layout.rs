@@ -0,0 +1,429 @@
+use std::time::Duration;
+
+use embedded_graphics::prelude::Size;
+use serde::Deserialize;
+use thiserror::Error;
+use uom::si::electric_potential::volt;
+
+use crate::grid::{CellAlign, GridLayout};
+use crate::history::History;
+use crate::refresh::RefreshPolicy;
+use crate::state::{AppState, NetworkStatus, SetpointStatus, Voltage};
+use crate::units::{TemperatureSettings, TemperatureUnit};
+
+#[derive(Error, Debug)]
+pub enum LayoutError {
+    #[error("unable to parse layout: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("unable to read layout: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("layout has no pages")]
+    NoPages,
+    #[error("no page named {0}")]
+    UnknownPage(String),
+    #[error("setpoint range {min}..={max} is empty")]
+    SetpointRange { min: f32, max: f32 },
+    #[error("setpoint step {0} is not positive")]
+    SetpointStep(f32),
+}
+
+/// Screen description: which pages to build, which widgets they hold, what
+/// they show and where. Parsed from TOML, either embedded at compile time or
+/// loaded at runtime.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ScreenLayout {
+    #[serde(default)]
+    pub profile: Profile,
+    #[serde(default)]
+    pub orientation: Orientation,
+    pub pages: Vec<PageLayout>,
+    #[serde(default)]
+    pub refresh: RefreshPolicy,
+    #[serde(default)]
+    pub temperature: TemperatureSettings,
+    #[serde(default)]
+    pub grid: GridLayout,
+    #[serde(default)]
+    pub table: TableLayout,
+    #[serde(default)]
+    pub idle: IdleSettings,
+    #[serde(default)]
+    pub battery: BatterySettings,
+    #[serde(default)]
+    pub stale: StaleSettings,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PageLayout {
+    pub name: String,
+    /// Battery and network icons along the top edge
+    #[serde(default)]
+    pub status_bar: bool,
+    /// Hidden in the production profile until diagnostics are requested
+    #[serde(default)]
+    pub diagnostics: bool,
+    /// Shown instead of the other pages while idle, skipped when cycling pages
+    #[serde(default)]
+    pub idle: bool,
+    /// Overrides the screen-wide grid for this page
+    pub grid: Option<GridLayout>,
+    #[serde(default)]
+    pub widgets: Vec<WidgetConfig>,
+}
+
+/// Idle mode starts `timeout_secs` after the last button press, 0 disables it.
+/// The idle page is redrawn at most every `refresh_secs`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct IdleSettings {
+    pub timeout_secs: u64,
+    pub refresh_secs: u64,
+}
+
+impl Default for IdleSettings {
+    fn default() -> Self {
+        IdleSettings {
+            timeout_secs: 600,
+            refresh_secs: 900,
+        }
+    }
+}
+
+/// Below either threshold the firmware shows the low-battery screen and powers off
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BatterySettings {
+    /// State of charge, 0..1
+    pub critical_soc: f32,
+    pub critical_voltage: f32,
+}
+
+impl Default for BatterySettings {
+    fn default() -> Self {
+        BatterySettings {
+            critical_soc: 0.02,
+            critical_voltage: 3.3,
+        }
+    }
+}
+
+impl BatterySettings {
+    pub fn is_critical(&self, voltage: Voltage, state_of_charge: f32) -> bool {
+        state_of_charge <= self.critical_soc || voltage.get::<volt>() <= self.critical_voltage
+    }
+}
+
+/// Received values older than this are drawn dimmed, 0 never marks them stale
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct StaleSettings {
+    pub temp_sensor_secs: u64,
+    /// Age of the last setpoint received on the command topic
+    pub temp_setpoint_secs: u64,
+}
+
+impl Default for StaleSettings {
+    fn default() -> Self {
+        StaleSettings {
+            temp_sensor_secs: 30 * 60,
+            temp_setpoint_secs: 0,
+        }
+    }
+}
+
+impl StaleSettings {
+    /// `None` for sources that never go stale
+    pub fn max_age(&self, source: DataSource) -> Option<Duration> {
+        let secs = match source {
+            DataSource::TempSensor => self.temp_sensor_secs,
+            DataSource::TempSetpoint => self.temp_setpoint_secs,
+            _ => 0,
+        };
+        (secs > 0).then(|| Duration::from_secs(secs))
+    }
+}
+
+/// Production hides diagnostic pages and table rows until requested
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Profile {
+    #[default]
+    Development,
+    Production,
+}
+
+/// Panel is 960x540 natively, portrait turns it by 90°
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Orientation {
+    #[default]
+    Portrait,
+    Landscape,
+    PortraitFlipped,
+    LandscapeFlipped,
+}
+
+impl Orientation {
+    pub fn size(&self) -> Size {
+        match self {
+            Orientation::Portrait | Orientation::PortraitFlipped => Size::new(540, 960),
+            Orientation::Landscape | Orientation::LandscapeFlipped => Size::new(960, 540),
+        }
+    }
+}
+
+impl ScreenLayout {
+    pub fn from_toml(layout: &str) -> Result<ScreenLayout, LayoutError> {
+        let mut layout: ScreenLayout = toml::from_str(layout)?;
+        if layout.pages.is_empty() {
+            return Err(LayoutError::NoPages);
+        }
+        // Buttons and the restored setpoint clamp to the range, it has to hold a value
+        let (min, max) = layout.temperature.setpoint_range();
+        if min.is_nan() || max.is_nan() || min > max {
+            return Err(LayoutError::SetpointRange { min, max });
+        }
+        let step = layout.temperature.setpoint_step;
+        if step.is_nan() || step <= 0.0 {
+            return Err(LayoutError::SetpointStep(step));
+        }
+        layout.resolve_unit();
+        Ok(layout)
+    }
+
+    /// Replaces `{unit}` in labels and value units with the temperature unit symbol
+    fn resolve_unit(&mut self) {
+        let symbol = self.temperature.unit.symbol();
+        for w in self.pages.iter_mut().flat_map(|p| p.widgets.iter_mut()) {
+            w.label = w.label.replace("{unit}", symbol);
+            if let Some(unit) = &mut w.format.unit {
+                *unit = unit.replace("{unit}", symbol);
+            }
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<ScreenLayout, LayoutError> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct WidgetConfig {
+    pub kind: WidgetKind,
+    pub label: String,
+    pub source: DataSource,
+    #[serde(default)]
+    pub format: ValueFormat,
+    /// Grid cell of a seven-segment or sparkline widget. Widgets without a position
+    /// flow into the next free cells in declaration order. Table rows keep declaration order.
+    pub position: Option<Position>,
+    /// Number of grid columns the widget takes, 1 by default
+    pub span: Option<u32>,
+    /// Alignment inside the cell, centered horizontally and top aligned by default
+    pub halign: Option<CellAlign>,
+    pub valign: Option<CellAlign>,
+    /// Digit cells of a seven-segment widget, sign and decimal point come on top. 3 by default
+    pub digits: Option<u32>,
+    /// Plot area of a sparkline, `[width, height]`
+    pub size: Option<[u32; 2]>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WidgetKind {
+    SevenSegment,
+    TableRow,
+    Sparkline,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub row: u32,
+    pub column: u32,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DataSource {
+    LoopCounter,
+    TimeSinceBoot,
+    BattVoltage,
+    StateOfCharge,
+    InitialStateOfCharge,
+    StateOfChargeChangeRate,
+    TempSensor,
+    TempSetpoint,
+    RenderTime,
+    NetworkStatus,
+    FreeHeap,
+    /// Local time of day, once the firmware synced it
+    Clock,
+    /// Confirmed, pending or reverted
+    SetpointStatus,
+    /// Time since the sensor value was received
+    TempSensorAge,
+    /// Time since a setpoint was received on the command topic
+    TempSetpointAge,
+}
+
+/// Typed value of a source, formatted by `ValueFormat`. Sources without data yield `None`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum SourceValue {
+    Number(f64),
+    Duration(Duration),
+    /// Time of day
+    Clock(Duration),
+    /// Enum variants and other values without a numeric representation
+    Text(String),
+}
+
+impl DataSource {
+    pub(crate) fn value(
+        &self,
+        state: &AppState,
+        render_time: Duration,
+        unit: TemperatureUnit,
+    ) -> Option<SourceValue> {
+        use SourceValue::*;
+
+        match self {
+            DataSource::LoopCounter => Some(Number(state.loop_counter.into())),
+            DataSource::TimeSinceBoot => Some(SourceValue::Duration(state.time_since_boot)),
+            DataSource::BattVoltage => Some(Number(state.batt_voltage.get::<volt>().into())),
+            DataSource::StateOfCharge => Some(Number(state.state_of_charge.into())),
+            DataSource::InitialStateOfCharge => {
+                state.initial_state_of_charge.map(|v| Number(v.into()))
+            }
+            DataSource::StateOfChargeChangeRate => {
+                state.state_of_charge_change_rate.map(|v| Number(v.into()))
+            }
+            DataSource::TempSensor => state
+                .temp_sensor
+                .map(|t| Number(unit.value(t.value).into())),
+            DataSource::TempSetpoint => state.temp_setpoint.map(|t| Number(unit.value(t).into())),
+            DataSource::RenderTime => Some(Number(render_time.as_millis() as f64)),
+            DataSource::NetworkStatus => Some(Text(
+                match state.network_status {
+                    NetworkStatus::Initializing => "starting",
+                    NetworkStatus::WifiConnected => "wifi",
+                    NetworkStatus::MqttConnected => "mqtt",
+                    NetworkStatus::Error => "error",
+                }
+                .to_owned(),
+            )),
+            DataSource::FreeHeap => Some(Number((state.free_heap_bytes / 1024).into())),
+            DataSource::Clock => state.clock.map(SourceValue::Clock),
+            DataSource::SetpointStatus => Some(Text(
+                match state.setpoint_status {
+                    SetpointStatus::Confirmed => "confirmed",
+                    SetpointStatus::Pending { .. } => "pending",
+                    SetpointStatus::Reverted => "reverted",
+                }
+                .to_owned(),
+            )),
+            DataSource::TempSensorAge => {
+                DataSource::TempSensor.age(state).map(SourceValue::Duration)
+            }
+            DataSource::TempSetpointAge => DataSource::TempSetpoint
+                .age(state)
+                .map(SourceValue::Duration),
+        }
+    }
+
+    /// Time since an externally sourced value was received, `None` for other sources
+    pub(crate) fn age(&self, state: &AppState) -> Option<Duration> {
+        let now = state.time_since_boot;
+        match self {
+            DataSource::TempSensor => state.temp_sensor.map(|t| t.age(now)),
+            DataSource::TempSetpoint => state.temp_setpoint_confirmed.map(|t| t.age(now)),
+            _ => None,
+        }
+    }
+
+    /// Value changed on the device and not confirmed yet
+    pub(crate) fn pending(&self, state: &AppState) -> bool {
+        match self {
+            DataSource::TempSetpoint => {
+                matches!(state.setpoint_status, SetpointStatus::Pending { .. })
+            }
+            _ => false,
+        }
+    }
+
+    pub(crate) fn history<'a>(&self, state: &'a AppState) -> Option<&'a History> {
+        match self {
+            DataSource::TempSensor => Some(&state.temp_history),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Align {
+    #[default]
+    Left,
+    Right,
+}
+
+/// Table columns are sized to their widest text, label, value and unit are aligned separately
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TableLayout {
+    pub label_align: Align,
+    pub value_align: Align,
+    pub unit_align: Align,
+    pub column_gap: u32,
+}
+
+impl Default for TableLayout {
+    fn default() -> Self {
+        TableLayout {
+            label_align: Align::Left,
+            value_align: Align::Right,
+            unit_align: Align::Left,
+            column_gap: 16,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ValueFormat {
+    /// Decimals, seven-segment widgets drop them when the value doesn't fit
+    pub precision: usize,
+    /// Shown in the table's unit column
+    pub unit: Option<String>,
+    /// Shown when the source has no value yet
+    pub placeholder: String,
+}
+
+impl Default for ValueFormat {
+    fn default() -> Self {
+        ValueFormat {
+            precision: 0,
+            unit: None,
+            placeholder: "--.-".to_owned(),
+        }
+    }
+}
+
+impl ValueFormat {
+    /// Value with `precision` applied, without the unit.
+    /// Durations are shown as `h:mm:ss`, time of day as `hh:mm`, missing values as the placeholder.
+    pub(crate) fn text(&self, value: Option<SourceValue>) -> String {
+        match value {
+            Some(SourceValue::Number(v)) => format!("{:.*}", self.precision, v),
+            Some(SourceValue::Duration(d)) => {
+                let secs = d.as_secs();
+                format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+            }
+            Some(SourceValue::Clock(t)) => {
+                let minutes = t.as_secs() / 60;
+                format!("{:02}:{:02}", minutes / 60 % 24, minutes % 60)
+            }
+            Some(SourceValue::Text(t)) => t,
+            None => self.placeholder.clone(),
+        }
+    }
+}
@@ -1,8 +1,22 @@
+pub mod buttons;
+pub mod framebuffer;
+pub mod grid;
+pub mod history;
+pub mod layout;
 mod layout_adapter;
+mod low_battery;
+pub mod persist;
+pub mod recording;
+pub mod refresh;
 pub mod renderer;
+mod seven_segment;
+mod sparkline;
 pub mod state;
+mod status_bar;
 mod table;
+pub mod units;
 mod util;
+pub mod widget;
 
 // Fixes ./cargo-fix-all.sh
 // TODO: Find a better build script
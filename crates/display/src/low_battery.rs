@@ -0,0 +1,59 @@
+use embedded_graphics::{
+    pixelcolor::Gray4,
+    prelude::*,
+    primitives::{Line, PrimitiveStyle, Rectangle, RoundedRectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+use u8g2_fonts::{fonts, U8g2TextStyle};
+
+const BATTERY_SIZE: Size = Size::new(240, 120);
+const STROKE: u32 = 10;
+
+/// Last screen before the firmware powers off, stays on the panel without power
+pub(crate) fn draw<D: DrawTarget<Color = Gray4>>(target: &mut D) -> Result<(), D::Error> {
+    target.clear(Gray4::WHITE)?;
+    let area = target.bounding_box();
+    let center = area.center();
+
+    // Empty battery crossed out, above the text
+    let battery = Rectangle::with_center(
+        center - Point::new(0, BATTERY_SIZE.height as i32),
+        BATTERY_SIZE,
+    );
+    let outline = PrimitiveStyle::with_stroke(Gray4::BLACK, STROKE);
+    RoundedRectangle::with_equal_corners(battery, Size::new(16, 16))
+        .into_styled(outline)
+        .draw(target)?;
+    Rectangle::new(
+        battery.top_left + Point::new(battery.size.width as i32, battery.size.height as i32 / 3),
+        Size::new(STROKE * 2, battery.size.height / 3),
+    )
+    .into_styled(PrimitiveStyle::with_fill(Gray4::BLACK))
+    .draw(target)?;
+    if let Some(bottom_right) = battery.bottom_right() {
+        Line::new(battery.top_left, bottom_right)
+            .into_styled(outline)
+            .draw(target)?;
+    }
+
+    let centered = TextStyleBuilder::new()
+        .alignment(Alignment::Center)
+        .baseline(Baseline::Top)
+        .build();
+    Text::with_text_style(
+        "Battery empty",
+        center + Point::new(0, 40),
+        U8g2TextStyle::new(fonts::u8g2_font_spleen32x64_mr, Gray4::BLACK),
+        centered,
+    )
+    .draw(target)?;
+    Text::with_text_style(
+        "Charge to turn back on",
+        center + Point::new(0, 120),
+        U8g2TextStyle::new(fonts::u8g2_font_spleen16x32_mr, Gray4::BLACK),
+        centered,
+    )
+    .draw(target)?;
+
+    Ok(())
+}
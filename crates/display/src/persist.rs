@@ -0,0 +1,182 @@
+//! State kept across reboots, stored by the firmware as one NVS blob.
+//!
+//! Little endian: `<version u8><unit u8><setpoint K f32><active page u8>
+//! <baseline soc f32><baseline span s u32><history len u16>` followed by
+//! `<age s u32><K f32>` per history bucket, oldest first. Missing values are NaN.
+//! Temperatures are kept in kelvin, the unit `uom` stores them in.
+
+use std::time::Duration;
+
+use thiserror::Error;
+use uom::si::{f32::ThermodynamicTemperature, thermodynamic_temperature::kelvin};
+
+use crate::state::AppState;
+use crate::units::{TemperatureSettings, TemperatureUnit};
+
+const VERSION: u8 = 1;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PersistError {
+    #[error("unsupported state version {0}")]
+    Version(u8),
+    #[error("saved state is truncated")]
+    Truncated,
+    #[error("invalid {0}")]
+    Invalid(&'static str),
+}
+
+/// State of charge measured on a previous boot, `span` of uptime before the save
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatteryBaseline {
+    pub state_of_charge: f32,
+    pub span: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Persisted {
+    /// Unit of the layout the state was saved with
+    pub unit: TemperatureUnit,
+    /// Confirmed setpoint if there is one, the shown one otherwise
+    pub setpoint: Option<ThermodynamicTemperature>,
+    pub active_page: usize,
+    pub battery_baseline: Option<BatteryBaseline>,
+    /// Age of each history bucket at the save, oldest first
+    pub temp_history: Vec<(Duration, ThermodynamicTemperature)>,
+}
+
+impl Persisted {
+    pub fn from_state(state: &AppState, unit: TemperatureUnit) -> Persisted {
+        Persisted {
+            unit,
+            setpoint: state
+                .temp_setpoint_confirmed
+                .map(|t| t.value)
+                .or(state.temp_setpoint),
+            active_page: state.active_page,
+            battery_baseline: state.initial_state_of_charge.map(|state_of_charge| {
+                BatteryBaseline {
+                    state_of_charge,
+                    span: state.battery_baseline_span + state.time_since_boot,
+                }
+            }),
+            temp_history: state.temp_history.ages(state.time_since_boot).collect(),
+        }
+    }
+
+    /// Restores the saved state at boot, the setpoint is fitted to the current policy
+    pub fn apply(&self, state: &mut AppState, temperature: &TemperatureSettings) {
+        // Saved setpoint is the confirmed one, an unconfirmed press reverts to it
+        if let Some(setpoint) = self.setpoint {
+            state.set_temp_setpoint(temperature.step_setpoint(setpoint, 0));
+        }
+        state.active_page = self.active_page;
+        if let Some(baseline) = self.battery_baseline {
+            state.initial_state_of_charge = Some(baseline.state_of_charge);
+            state.battery_baseline_span = baseline.span;
+        }
+        state
+            .temp_history
+            .restore(self.temp_history.iter().copied());
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let to_kelvin =
+            |t: Option<ThermodynamicTemperature>| t.map_or(f32::NAN, |t| t.get::<kelvin>());
+        let secs = |d: Duration| u32::try_from(d.as_secs()).unwrap_or(u32::MAX);
+        let history_len = self.temp_history.len().min(u16::MAX as usize);
+
+        let mut out = Vec::with_capacity(17 + history_len * 8);
+        out.push(VERSION);
+        out.push(match self.unit {
+            TemperatureUnit::Fahrenheit => 0,
+            TemperatureUnit::Celsius => 1,
+        });
+        out.extend(to_kelvin(self.setpoint).to_le_bytes());
+        out.push(self.active_page.min(u8::MAX as usize) as u8);
+        let (soc, span) = self
+            .battery_baseline
+            .map_or((f32::NAN, 0), |b| (b.state_of_charge, secs(b.span)));
+        out.extend(soc.to_le_bytes());
+        out.extend(span.to_le_bytes());
+        out.extend((history_len as u16).to_le_bytes());
+        // Newest buckets are kept when there are too many
+        let skip = self.temp_history.len() - history_len;
+        for (age, value) in &self.temp_history[skip..] {
+            out.extend(secs(*age).to_le_bytes());
+            out.extend(to_kelvin(Some(*value)).to_le_bytes());
+        }
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Persisted, PersistError> {
+        let mut r = Reader(bytes);
+        let version = r.u8()?;
+        if version != VERSION {
+            return Err(PersistError::Version(version));
+        }
+
+        let unit = match r.u8()? {
+            0 => TemperatureUnit::Fahrenheit,
+            1 => TemperatureUnit::Celsius,
+            _ => return Err(PersistError::Invalid("unit")),
+        };
+        let setpoint = r.temperature()?;
+        let active_page = r.u8()?.into();
+        let soc = r.f32()?;
+        let span = Duration::from_secs(r.u32()?.into());
+        let battery_baseline = (!soc.is_nan()).then_some(BatteryBaseline {
+            state_of_charge: soc,
+            span,
+        });
+
+        let history_len = r.u16()?;
+        let mut temp_history = Vec::with_capacity(history_len.into());
+        for _ in 0..history_len {
+            let age = Duration::from_secs(r.u32()?.into());
+            let value = r.temperature()?.ok_or(PersistError::Invalid("history"))?;
+            temp_history.push((age, value));
+        }
+
+        Ok(Persisted {
+            unit,
+            setpoint,
+            active_page,
+            battery_baseline,
+            temp_history,
+        })
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], PersistError> {
+        let (head, rest) = self
+            .0
+            .split_first_chunk::<N>()
+            .ok_or(PersistError::Truncated)?;
+        self.0 = rest;
+        Ok(*head)
+    }
+
+    fn u8(&mut self) -> Result<u8, PersistError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, PersistError> {
+        self.take().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, PersistError> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> Result<f32, PersistError> {
+        self.take().map(f32::from_le_bytes)
+    }
+
+    fn temperature(&mut self) -> Result<Option<ThermodynamicTemperature>, PersistError> {
+        let v = self.f32()?;
+        Ok((!v.is_nan()).then(|| ThermodynamicTemperature::new::<kelvin>(v)))
+    }
+}
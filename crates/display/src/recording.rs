@@ -0,0 +1,176 @@
+//! One-line text records of `AppState`, written by the firmware to the serial
+//! log or MQTT and replayed by the simulator.
+//!
+//! `@state,4,<uptime ms>,<loop counter>,<battery mV>,<soc>,<initial soc>,<soc rate>,
+//! <charging>,<network>,<free heap>,<page>,<diagnostics>,<temp °F>,<setpoint °F>,
+//! <idle>,<clock s>,<setpoint status>,<confirmed setpoint °F>,<temp received ms>,
+//! <confirmed setpoint received ms>`
+//!
+//! Setpoint status is `C`onfirmed, `P`ending or `R`everted, a pending setpoint
+//! is replayed as changed at the record's uptime.
+//!
+//! Missing values are empty fields. Records may be preceded by anything, e.g. a log prefix.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use thiserror::Error;
+use uom::si::{
+    electric_potential::millivolt, f32::ThermodynamicTemperature,
+    thermodynamic_temperature::degree_fahrenheit,
+};
+
+use crate::state::{AppState, NetworkStatus, Received, SetpointStatus, Voltage};
+
+pub const RECORD_TAG: &str = "@state,";
+const VERSION: &str = "4";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum RecordError {
+    #[error("line holds no state record")]
+    NoRecord,
+    #[error("unsupported record version {0}")]
+    Version(String),
+    #[error("record is missing {0}")]
+    Missing(&'static str),
+    #[error("invalid {0}: {1:?}")]
+    Invalid(&'static str, String),
+}
+
+pub fn encode(state: &AppState) -> String {
+    let opt = |v: Option<String>| v.unwrap_or_default();
+    let fahrenheit = |t: Option<ThermodynamicTemperature>| {
+        opt(t.map(|t| format!("{:.2}", t.get::<degree_fahrenheit>())))
+    };
+    let received_at = |t: Option<Received<_>>| opt(t.map(|t| t.at.as_millis().to_string()));
+    let network = match state.network_status {
+        NetworkStatus::Initializing => 'I',
+        NetworkStatus::WifiConnected => 'W',
+        NetworkStatus::MqttConnected => 'M',
+        NetworkStatus::Error => 'E',
+    };
+
+    let fields = [
+        VERSION.to_owned(),
+        state.time_since_boot.as_millis().to_string(),
+        state.loop_counter.to_string(),
+        format!("{:.0}", state.batt_voltage.get::<millivolt>()),
+        format!("{:.3}", state.state_of_charge),
+        opt(state.initial_state_of_charge.map(|v| format!("{v:.3}"))),
+        opt(state.state_of_charge_change_rate.map(|v| format!("{v:.5}"))),
+        u8::from(state.charging).to_string(),
+        network.to_string(),
+        state.free_heap_bytes.to_string(),
+        state.active_page.to_string(),
+        u8::from(state.show_diagnostics).to_string(),
+        fahrenheit(state.temp_sensor.map(|t| t.value)),
+        fahrenheit(state.temp_setpoint),
+        u8::from(state.idle).to_string(),
+        opt(state.clock.map(|t| t.as_secs().to_string())),
+        match state.setpoint_status {
+            SetpointStatus::Confirmed => "C",
+            SetpointStatus::Pending { .. } => "P",
+            SetpointStatus::Reverted => "R",
+        }
+        .to_owned(),
+        fahrenheit(state.temp_setpoint_confirmed.map(|t| t.value)),
+        received_at(state.temp_sensor),
+        received_at(state.temp_setpoint_confirmed),
+    ];
+    format!("{RECORD_TAG}{}", fields.join(","))
+}
+
+/// Applies the record found in `line` onto `state`. Sensor readings go through
+/// `set_temp_sensor`, so the history fills up as the records are replayed.
+/// `state` is left untouched when the record is invalid.
+pub fn apply(line: &str, state: &mut AppState) -> Result<(), RecordError> {
+    let start = line.find(RECORD_TAG).ok_or(RecordError::NoRecord)?;
+    let mut fields = line[start + RECORD_TAG.len()..].trim_end().split(',');
+
+    let version = fields.next().unwrap_or_default();
+    if version != VERSION {
+        return Err(RecordError::Version(version.to_owned()));
+    }
+
+    let mut next = state.clone();
+    next.time_since_boot = Duration::from_millis(parse(&mut fields, "uptime")?);
+    next.loop_counter = parse(&mut fields, "loop counter")?;
+    next.batt_voltage = Voltage::new::<millivolt>(parse(&mut fields, "battery voltage")?);
+    next.state_of_charge = parse(&mut fields, "state of charge")?;
+    next.initial_state_of_charge = parse_opt(&mut fields, "initial state of charge")?;
+    next.state_of_charge_change_rate = parse_opt(&mut fields, "state of charge rate")?;
+    next.charging = parse::<u8>(&mut fields, "charging")? != 0;
+    next.network_status = match field(&mut fields, "network status")? {
+        "I" => NetworkStatus::Initializing,
+        "W" => NetworkStatus::WifiConnected,
+        "M" => NetworkStatus::MqttConnected,
+        "E" => NetworkStatus::Error,
+        v => return Err(RecordError::Invalid("network status", v.to_owned())),
+    };
+    next.free_heap_bytes = parse(&mut fields, "free heap")?;
+    next.active_page = parse(&mut fields, "page")?;
+    next.show_diagnostics = parse::<u8>(&mut fields, "diagnostics")? != 0;
+    let fahrenheit = |t: Option<f32>| t.map(ThermodynamicTemperature::new::<degree_fahrenheit>);
+    let temp = fahrenheit(parse_opt(&mut fields, "temperature")?);
+    next.temp_setpoint = fahrenheit(parse_opt(&mut fields, "setpoint")?);
+    next.idle = parse::<u8>(&mut fields, "idle")? != 0;
+    next.clock = parse_opt(&mut fields, "clock")?.map(Duration::from_secs);
+    next.setpoint_status = match field(&mut fields, "setpoint status")? {
+        "C" => SetpointStatus::Confirmed,
+        "P" => SetpointStatus::Pending {
+            since: next.time_since_boot,
+        },
+        "R" => SetpointStatus::Reverted,
+        v => return Err(RecordError::Invalid("setpoint status", v.to_owned())),
+    };
+    let confirmed = fahrenheit(parse_opt(&mut fields, "confirmed setpoint")?);
+    let temp_at = parse_opt(&mut fields, "temperature received")?.map(Duration::from_millis);
+    let confirmed_at = parse_opt(&mut fields, "setpoint received")?.map(Duration::from_millis);
+
+    match temp {
+        Some(t) => {
+            next.set_temp_sensor(t);
+            next.temp_sensor = Some(Received {
+                value: t,
+                at: temp_at.unwrap_or(next.time_since_boot),
+            });
+        }
+        None => next.temp_sensor = None,
+    }
+    next.temp_setpoint_confirmed = confirmed.map(|value| Received {
+        value,
+        at: confirmed_at.unwrap_or(next.time_since_boot),
+    });
+    next.refresh_updated_counter();
+    *state = next;
+    Ok(())
+}
+
+fn field<'a>(
+    fields: &mut impl Iterator<Item = &'a str>,
+    name: &'static str,
+) -> Result<&'a str, RecordError> {
+    fields.next().ok_or(RecordError::Missing(name))
+}
+
+fn parse<'a, T: FromStr>(
+    fields: &mut impl Iterator<Item = &'a str>,
+    name: &'static str,
+) -> Result<T, RecordError> {
+    let v = field(fields, name)?;
+    v.parse()
+        .map_err(|_| RecordError::Invalid(name, v.to_owned()))
+}
+
+fn parse_opt<'a, T: FromStr>(
+    fields: &mut impl Iterator<Item = &'a str>,
+    name: &'static str,
+) -> Result<Option<T>, RecordError> {
+    match field(fields, name)? {
+        "" => Ok(None),
+        v => v
+            .parse()
+            .map(Some)
+            .map_err(|_| RecordError::Invalid(name, v.to_owned())),
+    }
+}
@@ -0,0 +1,171 @@
+use std::time::{Duration, Instant};
+
+use embedded_graphics::primitives::Rectangle;
+use serde::Deserialize;
+
+/// Panel update waveforms, ordered from the fastest to the cleanest one.
+/// Device side maps them onto the controller waveform modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Waveform {
+    /// Fast black/white update, accumulates ghosting
+    A2,
+    /// Grayscale content on white background
+    Gl16,
+    /// Flashing full grayscale update, clears ghosting
+    Gc16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyRegion {
+    pub area: Rectangle,
+    pub waveform: Waveform,
+}
+
+impl DirtyRegion {
+    pub fn new(area: Rectangle, waveform: Waveform) -> DirtyRegion {
+        DirtyRegion { area, waveform }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RefreshPolicy {
+    /// Partial refreshes before the whole panel gets a clean refresh, 0 disables it
+    pub full_refresh_after: u32,
+    /// Clean refresh waits until no button was pressed for this long
+    pub quiet_period_secs: Option<u64>,
+}
+
+impl Default for RefreshPolicy {
+    fn default() -> Self {
+        RefreshPolicy {
+            full_refresh_after: 50,
+            quiet_period_secs: None,
+        }
+    }
+}
+
+pub(crate) struct GhostingTracker {
+    policy: RefreshPolicy,
+    partial_refreshes: u32,
+    last_refresh: Option<Instant>,
+    last_input: Option<Instant>,
+}
+
+impl GhostingTracker {
+    pub fn new(policy: RefreshPolicy) -> GhostingTracker {
+        GhostingTracker {
+            policy,
+            partial_refreshes: 0,
+            last_refresh: None,
+            last_input: None,
+        }
+    }
+
+    pub fn partial_refresh(&mut self, now: Instant) {
+        self.partial_refreshes += 1;
+        self.last_refresh = Some(now);
+    }
+
+    pub fn full_refresh(&mut self, now: Instant) {
+        self.partial_refreshes = 0;
+        self.last_refresh = Some(now);
+    }
+
+    /// Button press, the flashing clean refresh keeps off while someone uses the screen
+    pub fn input(&mut self, now: Instant) {
+        self.last_input = Some(now);
+    }
+
+    /// When the clean refresh is due, `None` until enough partial refreshes piled up
+    pub fn clean_refresh_at(&self) -> Option<Instant> {
+        if self.policy.full_refresh_after == 0
+            || self.partial_refreshes < self.policy.full_refresh_after
+        {
+            return None;
+        }
+
+        let quiet_until = self
+            .policy
+            .quiet_period_secs
+            .zip(self.last_input)
+            .map(|(quiet, input)| input + Duration::from_secs(quiet));
+        self.last_refresh.max(quiet_until)
+    }
+
+    pub fn clean_refresh_due(&self, now: Instant) -> bool {
+        self.clean_refresh_at().is_some_and(|at| at <= now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker(full_refresh_after: u32, quiet_period_secs: Option<u64>) -> GhostingTracker {
+        GhostingTracker::new(RefreshPolicy {
+            full_refresh_after,
+            quiet_period_secs,
+        })
+    }
+
+    #[test]
+    fn clean_refresh_after_partial_count() {
+        let now = Instant::now();
+        let mut t = tracker(3, None);
+        for _ in 0..2 {
+            t.partial_refresh(now);
+            assert!(!t.clean_refresh_due(now));
+        }
+        t.partial_refresh(now);
+        assert!(t.clean_refresh_due(now));
+        assert_eq!(t.clean_refresh_at(), Some(now));
+
+        t.full_refresh(now);
+        assert!(!t.clean_refresh_due(now));
+        assert_eq!(t.clean_refresh_at(), None);
+    }
+
+    #[test]
+    fn zero_count_disables_clean_refresh() {
+        let now = Instant::now();
+        let mut t = tracker(0, None);
+        for _ in 0..100 {
+            t.partial_refresh(now);
+        }
+        assert!(!t.clean_refresh_due(now));
+    }
+
+    #[test]
+    fn clean_refresh_waits_for_quiet_period() {
+        let start = Instant::now();
+        let secs = |s| start + Duration::from_secs(s);
+        let mut t = tracker(2, Some(60));
+        t.input(start);
+        t.partial_refresh(start);
+        t.partial_refresh(secs(10));
+
+        assert_eq!(t.clean_refresh_at(), Some(secs(60)));
+        assert!(!t.clean_refresh_due(secs(59)));
+        assert!(t.clean_refresh_due(secs(60)));
+
+        // Another press restarts the quiet period
+        t.input(secs(50));
+        assert!(!t.clean_refresh_due(secs(100)));
+        assert!(t.clean_refresh_due(secs(110)));
+    }
+
+    #[test]
+    fn frequent_updates_dont_defer_clean_refresh() {
+        let start = Instant::now();
+        let secs = |s| start + Duration::from_secs(s);
+        let mut t = tracker(3, Some(30));
+        t.input(start);
+        // Sensor updates every 10 s, more often than the quiet period
+        for s in [10, 20, 30, 40] {
+            t.partial_refresh(secs(s));
+        }
+        assert_eq!(t.clean_refresh_at(), Some(secs(40)));
+        assert!(t.clean_refresh_due(secs(40)));
+    }
+}
@@ -1,21 +1,27 @@
-use std::marker::PhantomPinned;
 use std::time::Duration;
 use std::time::Instant;
 
-use eg_seven_segment::{SevenSegmentStyle, SevenSegmentStyleBuilder};
-use embedded_graphics::{pixelcolor::Gray4, prelude::*, primitives::Rectangle, text::Text};
+use embedded_graphics::{pixelcolor::Gray4, prelude::*, primitives::Rectangle};
 
-use embedded_layout::layout::linear::FixedMargin;
 use embedded_layout::layout::linear::LinearLayout;
-use embedded_layout::{prelude::*, ViewGroup};
-use u8g2_fonts::fonts;
-use u8g2_fonts::U8g2TextStyle;
-use uom::si::quantities::ThermodynamicTemperature;
-use uom::si::{electric_potential::volt, thermodynamic_temperature::degree_fahrenheit};
-
+use embedded_layout::prelude::*;
+use embedded_layout::view_group::ViewGroupHelper;
+
+use crate::grid::{Cell, CellAlign, GridLayout};
+use crate::layout::{
+    LayoutError, PageLayout, Position, Profile, ScreenLayout, StaleSettings, TableLayout,
+    WidgetConfig, WidgetKind,
+};
+use crate::low_battery;
+use crate::refresh::{DirtyRegion, GhostingTracker, Waveform};
+use crate::seven_segment::{SegmentStyle, SevenSegment, DEFAULT_DIGITS};
+use crate::sparkline::Sparkline;
 use crate::state::AppState;
+use crate::status_bar::StatusBar;
 use crate::table::DisplayTable;
-use crate::util::RectExt2;
+use crate::units::TemperatureUnit;
+use crate::util::coalesce;
+use crate::widget::Widget;
 
 use thiserror::Error;
 
@@ -26,262 +32,397 @@ pub enum Error<DisplayError> {
     FontError(#[from] u8g2_fonts::Error<DisplayError>),
 }
 
-pub struct Renderer {
-    segment_renderer: eg_seven_segment::SevenSegmentStyle<Gray4>,
-    table: DisplayTable<(AppState, Duration), Gray4>,
-    segmented_displays: Vec<Widget>,
+/// Dirty regions closer than this are refreshed together
+const DIRTY_MERGE_GAP: u32 = 16;
+const DEFAULT_SPARKLINE_SIZE: [u32; 2] = [400, 120];
+
+pub struct Renderer<D: DrawTarget<Color = Gray4>> {
+    segment_style: SegmentStyle,
+    pages: Vec<Page<D>>,
+    active_page: usize,
+    ghosting: GhostingTracker,
+    unit: TemperatureUnit,
+    stale: StaleSettings,
+    profile: Profile,
+    bounding_box: Rectangle,
+    /// Table rows and diagnostic pages are visible
+    diagnostics: bool,
+    /// Widgets moved since the last draw, screen is cleared and redrawn
+    layout_changed: bool,
+    /// Least time between two draws of the idle page
+    idle_refresh: Duration,
+    /// Uptime of the last idle page draw
+    idle_drawn_at: Option<Duration>,
+    /// Low-battery screen is on the panel
+    low_battery_shown: bool,
+    /// Button presses seen by the last draw, a change is user input
+    button_presses: u32,
 
     render_time: Duration,
     full_render: bool,
 }
 
+struct Page<D: DrawTarget<Color = Gray4>> {
+    name: String,
+    diagnostics: bool,
+    idle: bool,
+    grid: GridLayout,
+    table: DisplayTable<(AppState, Duration), Gray4>,
+    status_bar: Option<Slot<D>>,
+    widgets: Vec<Slot<D>>,
+}
+
 pub enum DrawResult {
-    Complete(Rectangle),
-    Partial(Rectangle),
+    /// Whole screen needs a clean refresh: after a page switch or to clear ghosting
+    Full(DirtyRegion),
+    /// Dirty regions, coalesced so that only nearby changes share a refresh
+    Complete(Vec<DirtyRegion>),
+    Partial(Vec<DirtyRegion>),
     None,
 }
 
-#[derive(ViewGroup)]
-pub enum WidgetType<'a> {
-    LabeledSevenSeg(
-        Text<'a, U8g2TextStyle<Gray4>>,
-        Text<'a, SevenSegmentStyle<Gray4>>,
-    ),
+/// Widget in its grid cell, redrawn after `refresh` reported a change
+struct Slot<D: DrawTarget<Color = Gray4>> {
+    widget: Box<dyn Widget<D>>,
+    cell: Cell,
+    changed: bool,
 }
 
-impl<'a> WidgetType<'a> {
-    fn draw<T: DrawTarget<Color = Gray4>>(
-        &self,
-        dynamic_only: bool,
-        target: &mut T,
-    ) -> Result<Option<Rectangle>, T::Error> {
-        match self {
-            WidgetType::LabeledSevenSeg(label, value) => {
-                value.draw(target)?;
-                let mut bb = Some(value.bounding_box());
-                if !dynamic_only {
-                    label.draw(target)?;
-                    bb.merge_rect(label.bounding_box());
-                }
-                Ok(bb)
-            }
+impl<D: DrawTarget<Color = Gray4>> Slot<D> {
+    fn new(widget: Box<dyn Widget<D>>, cell: Cell) -> Slot<D> {
+        Slot {
+            widget,
+            cell,
+            changed: false,
         }
     }
-}
-
-enum WidgetDataSource {
-    State(fn(&AppState) -> String),
-}
-
-pub struct Widget {
-    pub w_type: WidgetType<'static>,
-    value: String,
-    state_source: WidgetDataSource,
-    changed: bool,
-    _marker: PhantomPinned,
-}
 
-impl Widget {
-    fn refresh_from_state(&mut self, state: &AppState) {
-        if let Some(new_value) = match self.state_source {
-            WidgetDataSource::State(f) => Some(f(state)),
-        } {
-            let changed = new_value != self.value;
-
-            if changed {
-                self.changed = true;
-                self.value = new_value;
-                match &mut self.w_type {
-                    WidgetType::LabeledSevenSeg(label, value) => {
-                        log::info!(
-                            "Widget change detected: {}, changed to {}",
-                            label.text,
-                            self.value
-                        );
-
-                        // Hell of self-referncial structures,
-                        // One-day: fixme
-                        value.text =
-                            unsafe { std::mem::transmute::<&str, &str>(self.value.as_str()) };
-                    }
-                }
-            }
-        }
+    fn refresh_from_state(&mut self, state: &AppState, render_time: Duration) {
+        self.changed |= self.widget.refresh(state, render_time);
     }
 
-    fn draw<T: DrawTarget<Color = Gray4>>(
+    fn draw(
         &mut self,
         dynamic_only: bool,
-        target: &mut T,
-    ) -> Result<Option<Rectangle>, T::Error> {
+        target: &mut D,
+    ) -> Result<Option<DirtyRegion>, D::Error> {
         if !dynamic_only || self.changed {
             self.changed = false;
-            Ok(self.w_type.draw(dynamic_only, target)?)
+            let area = self.widget.draw(target, dynamic_only)?;
+            Ok(area.map(|area| DirtyRegion::new(area, self.widget.waveform())))
         } else {
             Ok(None)
         }
     }
 }
 
-impl View for Widget {
+impl<D: DrawTarget<Color = Gray4>> View for Slot<D> {
     fn translate_impl(&mut self, by: Point) {
-        self.w_type.translate_impl(by);
+        self.widget.translate_impl(by);
     }
 
     fn bounds(&self) -> Rectangle {
-        self.w_type.bounds()
+        self.widget.bounds()
     }
 }
 
-impl Renderer {
-    fn txt_seven_segment(&self, label: &'static str, source: WidgetDataSource) -> Widget {
-        let label = Text::new(
-            label,
-            Point::zero(),
-            U8g2TextStyle::new(fonts::u8g2_font_spleen16x32_mr, Gray4::BLACK),
-        );
-        let mut display = Text::new("--.-", Point::zero(), self.segment_renderer);
+impl<D: DrawTarget<Color = Gray4>> Page<D> {
+    fn widgets_mut(&mut self) -> impl Iterator<Item = &mut Slot<D>> {
+        self.status_bar.iter_mut().chain(self.widgets.iter_mut())
+    }
 
-        display.align_to_mut(&label, horizontal::Left, vertical::TopToBottom);
+    fn update_layout(&mut self, bounding_box: &Rectangle, show_table: bool) {
+        let mut content = *bounding_box;
+        if let Some(status_bar) = &mut self.status_bar {
+            status_bar.align_to_mut(bounding_box, horizontal::Left, vertical::Top);
+            let height = status_bar.bounds().size.height;
+            content.top_left.y += height as i32;
+            content.size.height = content.size.height.saturating_sub(height);
+        }
 
-        Widget {
-            w_type: WidgetType::LabeledSevenSeg(label, display),
-            state_source: source,
-            changed: false,
-            value: String::new(),
-            _marker: PhantomPinned,
+        // Without the table widgets get the whole page and are centered on it
+        let valign = if show_table {
+            CellAlign::Start
+        } else {
+            CellAlign::Center
+        };
+        let items: Vec<(Cell, Size)> = self
+            .widgets
+            .iter()
+            .map(|w| (w.cell, w.bounds().size))
+            .collect();
+        let (positions, grid) = self.grid.arrange(&content, &items, valign);
+        for (w, p) in self.widgets.iter_mut().zip(positions) {
+            let by = p - w.bounds().top_left;
+            w.translate_impl(by);
+        }
+
+        // Pages without table rows have nothing to arrange
+        if !show_table || self.table.items.is_empty() {
+            return;
         }
+
+        let table_top = if items.is_empty() {
+            grid.top_left
+        } else {
+            grid.top_left + Point::new(0, (grid.size.height + self.grid.row_gap) as i32)
+        };
+        let _ = LinearLayout::vertical(&mut self.table).arrange();
+        let table_bounds = ViewGroupHelper::bounds(&self.table);
+        (&mut self.table).translate(table_top - table_bounds.top_left);
     }
+}
 
-    pub fn new(bounding_box: &Rectangle) -> Renderer {
+impl<D: DrawTarget<Color = Gray4>> Renderer<D> {
+    fn widget(&self, config: &WidgetConfig) -> Box<dyn Widget<D>> {
+        match config.kind {
+            WidgetKind::Sparkline => {
+                let [width, height] = config.size.unwrap_or(DEFAULT_SPARKLINE_SIZE);
+                Box::new(Sparkline::new(
+                    config.label.clone(),
+                    Size::new(width, height),
+                    config.source,
+                    self.unit,
+                ))
+            }
+            _ => Box::new(SevenSegment::new(
+                config.label.clone(),
+                self.segment_style,
+                config.source,
+                config.format.clone(),
+                config.digits.unwrap_or(DEFAULT_DIGITS),
+                self.unit,
+                self.stale.max_age(config.source),
+            )),
+        }
+    }
+
+    pub fn new(bounding_box: &Rectangle, layout: &ScreenLayout) -> Renderer<D> {
         let mut r = Renderer {
             render_time: Duration::ZERO,
-            segment_renderer: SevenSegmentStyleBuilder::new()
-                .digit_size(Size::new(35, 70))
-                .digit_spacing(6) // 5px spacing between digits
-                .segment_width(12) // 5px wide segments
-                .segment_color(Gray4::BLACK) // active segments are green
-                .inactive_segment_color(Gray4::WHITE)
-                .build(),
-            table: DisplayTable::new(Gray4::BLACK, Gray4::WHITE)
-                .expect("unable to create DisplayTable"),
-            segmented_displays: Vec::new(),
+            segment_style: SegmentStyle {
+                digit_size: Size::new(35, 70),
+                digit_spacing: 18,
+                segment_width: 12,
+                color: Gray4::BLACK,
+                inactive_color: Gray4::WHITE,
+                stale_color: Gray4::new(0x9),
+            },
+            pages: Vec::new(),
+            active_page: 0,
+            ghosting: GhostingTracker::new(layout.refresh.clone()),
+            unit: layout.temperature.unit,
+            stale: layout.stale.clone(),
+            profile: layout.profile,
+            bounding_box: *bounding_box,
+            diagnostics: layout.profile == Profile::Development,
+            layout_changed: false,
+            idle_refresh: Duration::from_secs(layout.idle.refresh_secs),
+            idle_drawn_at: None,
+            low_battery_shown: false,
+            button_presses: 0,
             full_render: true,
         };
 
-        r.init_widgets();
-        r.update_layout(bounding_box);
+        for page in &layout.pages {
+            let mut p = r.init_page(page, bounding_box.size.width, &layout.grid, &layout.table);
+            p.update_layout(bounding_box, r.diagnostics);
+            r.pages.push(p);
+        }
         r
     }
 
-    fn init_widgets(&mut self) {
-        let table = &mut self.table;
-
-        table.add_item("Counter", |s| format!("{:<5}", s.0.loop_counter));
-        table.add_item("Time", |s| {
-            format!("{:<6} s", s.0.time_since_boot.as_secs())
-        });
-        table.add_item("Voltage", |s| {
-            format!("{:<6.4} V", s.0.batt_voltage.get::<volt>())
-        });
-        table.add_item("SOC", |s| format!("{:<3.2}", s.0.state_of_charge));
-        table.add_item("iSOC", |s| {
-            format!("{:<3.2}", s.0.initial_state_of_charge.unwrap_or(0_f32))
-        });
-        table.add_item("SOC d-rate", |s| {
-            format!(
-                "{:<7.4} 1/hr",
-                s.0.state_of_charge_change_rate.unwrap_or(0_f32)
-            )
-        });
-        table.add_item("Temp", |s| {
-            format!(
-                "{:<3.1} °F",
-                s.0.temp_sensor
-                    .map_or(0.0_f32, |t| t.get::<degree_fahrenheit>())
-            )
-        });
-        table.add_item("Setpoint", |s| {
-            format!(
-                "{:<3.1} °F",
-                s.0.temp_setpoint
-                    .map_or(0.0_f32, |t| t.get::<degree_fahrenheit>())
-            )
-        });
-        table.add_item("Render time", |s| format!("{:<6} ms", s.1.as_millis()));
-        table.add_item("Net", |s| format!("{:<6?}", s.0.network_status));
-        table.add_item("Heap free", |s| {
-            format!("{:<6} kb", s.0.free_heap_bytes / 1024)
-        });
-
-        self.segmented_displays.push(self.txt_seven_segment(
-            "temp °F",
-            WidgetDataSource::State(|s| temp_str(s.temp_sensor)),
-        ));
-        self.segmented_displays.push(self.txt_seven_segment(
-            "setpoint °F",
-            WidgetDataSource::State(|s| temp_str(s.temp_setpoint)),
-        ));
+    /// When the next draw will clean-refresh the whole panel even without changes,
+    /// the display loop wakes up for it
+    pub fn clean_refresh_at(&self) -> Option<Instant> {
+        self.ghosting.clean_refresh_at()
     }
 
-    fn update_layout(&mut self, bounding_box: &Rectangle) {
-        let mut v: Vec<Views<'_, Widget>> = Vec::new();
-
-        let mut remaining = self.segmented_displays.as_mut_slice();
-        let spacing = FixedMargin(62);
-        while remaining.len() > 1 {
-            let (l, r) = remaining.split_at_mut(2);
-            remaining = r;
-            let _ = LinearLayout::horizontal(Views::new(l))
-                .with_spacing(spacing)
-                .arrange();
+    /// Adds a widget built outside this crate to the named page
+    pub fn add_widget(
+        &mut self,
+        page: &str,
+        position: impl Into<Cell>,
+        widget: Box<dyn Widget<D>>,
+    ) -> Result<(), LayoutError> {
+        let p = self
+            .pages
+            .iter_mut()
+            .find(|p| p.name == page)
+            .ok_or_else(|| LayoutError::UnknownPage(page.to_owned()))?;
+        p.widgets.push(Slot::new(widget, position.into()));
+        p.update_layout(&self.bounding_box, self.diagnostics);
+        self.layout_changed = true;
+        Ok(())
+    }
 
-            v.push(Views::new(l));
+    fn init_page(
+        &self,
+        layout: &PageLayout,
+        width: u32,
+        grid: &GridLayout,
+        table: &TableLayout,
+    ) -> Page<D> {
+        let grid = layout.grid.clone().unwrap_or_else(|| grid.clone());
+        let columns = grid.columns.max(1);
+        let mut page = Page {
+            name: layout.name.clone(),
+            diagnostics: layout.diagnostics,
+            idle: layout.idle,
+            grid,
+            table: DisplayTable::new(Gray4::BLACK, Gray4::WHITE, table.clone()),
+            status_bar: layout.status_bar.then(|| {
+                Slot::new(
+                    Box::new(StatusBar::new(width)),
+                    Position { row: 0, column: 0 }.into(),
+                )
+            }),
+            widgets: Vec::new(),
+        };
+        // Next free cell for widgets without a position
+        let mut cursor = Position { row: 0, column: 0 };
+
+        for w in &layout.widgets {
+            match w.kind {
+                WidgetKind::TableRow => {
+                    let (source, unit) = (w.source, self.unit);
+                    page.table.add_item(
+                        w.label.clone(),
+                        w.format.clone(),
+                        Box::new(move |s: &(AppState, Duration)| source.value(&s.0, s.1, unit)),
+                    );
+                }
+                WidgetKind::SevenSegment | WidgetKind::Sparkline => {
+                    let span = w.span.unwrap_or(1).clamp(1, columns);
+                    let position = w.position.unwrap_or_else(|| {
+                        if cursor.column + span > columns {
+                            cursor = Position {
+                                row: cursor.row + 1,
+                                column: 0,
+                            };
+                        }
+                        cursor
+                    });
+                    cursor = Position {
+                        row: position.row,
+                        column: position.column + span,
+                    };
+
+                    let default = Cell::from(position);
+                    let cell = Cell {
+                        span,
+                        halign: w.halign.unwrap_or(default.halign),
+                        valign: w.valign.unwrap_or(default.valign),
+                        ..default
+                    };
+                    page.widgets.push(Slot::new(self.widget(w), cell));
+                }
+            }
         }
-        v.push(Views::new(remaining));
-
-        let segment_views = LinearLayout::vertical(Views::new(&mut v))
-            .with_spacing(spacing)
-            .arrange();
-
-        let segment_views = segment_views.align_to(bounding_box, horizontal::Center, vertical::Top);
-
-        let segment_views = segment_views.translate(Point::new(0, 62));
-
-        LinearLayout::vertical(&mut self.table)
-            .arrange()
-            .align_to(
-                &segment_views,
-                horizontal::NoAlignment,
-                vertical::TopToBottom,
-            )
-            .align_to(bounding_box, horizontal::Left, vertical::NoAlignment);
 
-        (&mut self.table).translate(Point::new(0, 26));
+        page
     }
 
-    pub fn draw<Display, DisplayError>(
+    pub fn draw(
         &mut self,
         state: &AppState,
-        display: &mut Display,
-    ) -> Result<DrawResult, Error<DisplayError>>
-    where
-        Display: DrawTarget<Color = Gray4, Error = DisplayError>,
-    {
+        display: &mut D,
+    ) -> Result<DrawResult, Error<D::Error>> {
         let render_start = Instant::now();
-        let mut bb = None;
-        for e in &mut self.segmented_displays {
-            e.refresh_from_state(state);
+
+        if state.battery_critical {
+            if std::mem::replace(&mut self.low_battery_shown, true) {
+                return Ok(DrawResult::None);
+            }
+            low_battery::draw(display)?;
+            // Everything is redrawn should the battery recover
+            self.layout_changed = true;
+            self.ghosting.full_refresh(Instant::now());
+            return Ok(DrawResult::Full(DirtyRegion::new(
+                display.bounding_box(),
+                Waveform::Gc16,
+            )));
+        }
+        self.low_battery_shown = false;
+
+        if std::mem::replace(&mut self.button_presses, state.button_presses) != state.button_presses
+        {
+            self.ghosting.input(render_start);
+        }
+
+        let diagnostics = self.profile == Profile::Development || state.show_diagnostics;
+        if diagnostics != self.diagnostics {
+            self.diagnostics = diagnostics;
+            self.layout_changed = true;
+            for p in &mut self.pages {
+                p.update_layout(&self.bounding_box, diagnostics);
+            }
+        }
+
+        let idle_page = state
+            .idle
+            .then(|| self.pages.iter().position(|p| p.idle))
+            .flatten();
+        let requested_page = match idle_page {
+            Some(idle_page) => idle_page,
+            None => {
+                let mut visible: Vec<usize> = (0..self.pages.len())
+                    .filter(|i| !self.pages[*i].idle)
+                    .filter(|i| diagnostics || !self.pages[*i].diagnostics)
+                    .collect();
+                if visible.is_empty() {
+                    visible = (0..self.pages.len()).collect();
+                }
+                visible[state.active_page % visible.len()]
+            }
+        };
+        let page_switch =
+            std::mem::take(&mut self.layout_changed) || requested_page != self.active_page;
+
+        // Idle page only follows the state every `idle_refresh`
+        if idle_page.is_some() {
+            let due = match self.idle_drawn_at {
+                Some(at) => state.time_since_boot >= at + self.idle_refresh,
+                None => true,
+            };
+            if !page_switch && !due {
+                return Ok(DrawResult::None);
+            }
+            self.idle_drawn_at = Some(state.time_since_boot);
+        } else {
+            self.idle_drawn_at = None;
+        }
+        if page_switch {
+            self.active_page = requested_page;
+            self.full_render = true;
+            display.clear(Gray4::WHITE)?;
+        }
+
+        let page = &mut self.pages[self.active_page];
+        if page_switch {
+            log::info!("Switched to page {}", page.name);
+        }
+
+        for e in page.widgets_mut() {
+            e.refresh_from_state(state, self.render_time);
         }
 
         let refresh_time = render_start - Instant::now();
 
-        self.table.update(&(state.clone(), self.render_time));
+        if self.diagnostics {
+            page.table.update(&(state.clone(), self.render_time));
+        }
 
-        for e in &mut self.segmented_displays {
-            bb.merge(&e.draw(!self.full_render, display)?);
+        let mut dirty = Vec::new();
+        for e in page.widgets_mut() {
+            dirty.extend(e.draw(!self.full_render, display)?);
         }
-        bb.merge(&self.table.draw(!self.full_render, display)?);
+        if self.diagnostics {
+            for area in page.table.draw(!self.full_render, display)? {
+                dirty.push(DirtyRegion::new(area, Waveform::Gl16));
+            }
+        }
+        let dirty = coalesce(dirty, DIRTY_MERGE_GAP);
 
         self.render_time = Instant::now() - render_start;
 
@@ -292,11 +433,21 @@ impl Renderer {
         );
 
         self.full_render = false;
-        Ok(bb.map(DrawResult::Complete).unwrap_or(DrawResult::None))
-    }
-}
 
-fn temp_str(temp: Option<ThermodynamicTemperature<f32>>) -> String {
-    temp.map(|t| format!("{:4.1}", t.get::<degree_fahrenheit>()))
-        .unwrap_or("--.-".to_owned())
+        let now = Instant::now();
+        if page_switch || self.ghosting.clean_refresh_due(now) {
+            self.ghosting.full_refresh(now);
+            return Ok(DrawResult::Full(DirtyRegion::new(
+                display.bounding_box(),
+                Waveform::Gc16,
+            )));
+        }
+
+        if dirty.is_empty() {
+            Ok(DrawResult::None)
+        } else {
+            self.ghosting.partial_refresh(now);
+            Ok(DrawResult::Complete(dirty))
+        }
+    }
 }
@@ -0,0 +1,385 @@
+use std::time::Duration;
+
+use embedded_graphics::{pixelcolor::Gray4, prelude::*, primitives::Rectangle, text::Text};
+use embedded_layout::prelude::*;
+use u8g2_fonts::{fonts, U8g2TextStyle};
+
+use crate::layout::{DataSource, SourceValue, ValueFormat};
+use crate::refresh::Waveform;
+use crate::state::AppState;
+use crate::units::TemperatureUnit;
+use crate::widget::Widget;
+
+/// Digit cells used when the layout doesn't set `digits`
+pub(crate) const DEFAULT_DIGITS: u32 = 3;
+
+/// Segment bits, `a` is the top segment, going clockwise, `g` is the middle one
+const SEG_A: u8 = 1 << 0;
+const SEG_B: u8 = 1 << 1;
+const SEG_C: u8 = 1 << 2;
+const SEG_D: u8 = 1 << 3;
+const SEG_E: u8 = 1 << 4;
+const SEG_F: u8 = 1 << 5;
+const SEG_G: u8 = 1 << 6;
+
+/// Stroke of the outlined segments of a pending value
+const PENDING_STROKE: u32 = 3;
+
+/// Size and colors of the digits
+#[derive(Debug, Clone, Copy)]
+pub struct SegmentStyle {
+    pub digit_size: Size,
+    /// Gap between digits, the decimal point is drawn in it
+    pub digit_spacing: u32,
+    pub segment_width: u32,
+    pub color: Gray4,
+    pub inactive_color: Gray4,
+    /// Lit segments of a stale value
+    pub stale_color: Gray4,
+}
+
+/// What the cells show: one glyph and decimal point per digit, sign in front
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Digits {
+    negative: bool,
+    cells: Vec<(u8, bool)>,
+}
+
+impl Digits {
+    /// Number right aligned in `digits` cells. Decimals are dropped until the
+    /// integer part fits, values that still don't fit show `HI` or `LO`.
+    fn number(value: f64, precision: usize, digits: usize) -> Digits {
+        if !value.is_finite() {
+            return Digits::text("Err", digits);
+        }
+
+        for precision in (0..=precision).rev() {
+            let text = format!("{:.*}", precision, value.abs());
+            let digit_count = text.chars().filter(char::is_ascii_digit).count();
+            if digit_count > digits {
+                continue;
+            }
+
+            let mut cells = vec![(0, false); digits - digit_count];
+            for c in text.chars() {
+                match c {
+                    '.' => {
+                        if let Some(last) = cells.last_mut() {
+                            last.1 = true;
+                        }
+                    }
+                    c => cells.push((glyph(c), false)),
+                }
+            }
+            // -0.0 after rounding has no sign
+            let negative = value < 0.0 && text.chars().any(|c| ('1'..='9').contains(&c));
+            return Digits { negative, cells };
+        }
+
+        Digits::text(if value < 0.0 { "LO" } else { "HI" }, digits)
+    }
+
+    /// Left aligned text, cut to the available cells
+    fn text(text: &str, digits: usize) -> Digits {
+        let mut cells: Vec<_> = text
+            .chars()
+            .take(digits)
+            .map(|c| (glyph(c), false))
+            .collect();
+        cells.resize(digits, (0, false));
+        Digits {
+            negative: false,
+            cells,
+        }
+    }
+
+    fn from_value(value: Option<SourceValue>, format: &ValueFormat, digits: usize) -> Digits {
+        match value {
+            Some(SourceValue::Number(v)) => Digits::number(v, format.precision, digits),
+            // Decimal point separates hours and minutes
+            Some(SourceValue::Clock(t)) if digits >= 4 => {
+                let minutes = t.as_secs() / 60;
+                let text = format!("{0:>1$}{2:02}", minutes / 60 % 24, digits - 2, minutes % 60);
+                let mut clock = Digits::text(&text, digits);
+                clock.cells[digits - 3].1 = true;
+                clock
+            }
+            Some(_) => Digits::text("Err", digits),
+            None => Digits::text(&"-".repeat(digits), digits),
+        }
+    }
+}
+
+fn glyph(c: char) -> u8 {
+    match c {
+        '0' | 'O' => SEG_A | SEG_B | SEG_C | SEG_D | SEG_E | SEG_F,
+        '1' => SEG_B | SEG_C,
+        '2' => SEG_A | SEG_B | SEG_D | SEG_E | SEG_G,
+        '3' => SEG_A | SEG_B | SEG_C | SEG_D | SEG_G,
+        '4' => SEG_B | SEG_C | SEG_F | SEG_G,
+        '5' => SEG_A | SEG_C | SEG_D | SEG_F | SEG_G,
+        '6' => SEG_A | SEG_C | SEG_D | SEG_E | SEG_F | SEG_G,
+        '7' => SEG_A | SEG_B | SEG_C,
+        '8' => SEG_A | SEG_B | SEG_C | SEG_D | SEG_E | SEG_F | SEG_G,
+        '9' => SEG_A | SEG_B | SEG_C | SEG_D | SEG_F | SEG_G,
+        '-' => SEG_G,
+        'E' => SEG_A | SEG_D | SEG_E | SEG_F | SEG_G,
+        'r' => SEG_E | SEG_G,
+        'H' => SEG_B | SEG_C | SEG_E | SEG_F | SEG_G,
+        // Left segments, so it doesn't read as 1
+        'I' => SEG_E | SEG_F,
+        'L' => SEG_D | SEG_E | SEG_F,
+        _ => 0,
+    }
+}
+
+/// Label on top of a large seven-segment value. The value takes the same
+/// space whatever it shows, so layout never shifts.
+pub struct SevenSegment {
+    label: String,
+    label_style: U8g2TextStyle<Gray4>,
+    style: SegmentStyle,
+    source: DataSource,
+    format: ValueFormat,
+    unit: TemperatureUnit,
+    value: Digits,
+    /// Value isn't confirmed yet, active segments are only outlined
+    pending: bool,
+    /// Received values older than this are stale
+    max_age: Option<Duration>,
+    stale: bool,
+    origin: Point,
+}
+
+impl SevenSegment {
+    pub fn new(
+        label: String,
+        style: SegmentStyle,
+        source: DataSource,
+        format: ValueFormat,
+        digits: u32,
+        unit: TemperatureUnit,
+        max_age: Option<Duration>,
+    ) -> SevenSegment {
+        let digits = digits.max(1) as usize;
+        SevenSegment {
+            label,
+            label_style: U8g2TextStyle::new(fonts::u8g2_font_spleen16x32_mr, Gray4::BLACK),
+            style,
+            source,
+            value: Digits::from_value(None, &format, digits),
+            format,
+            unit,
+            pending: false,
+            max_age,
+            stale: false,
+            origin: Point::zero(),
+        }
+    }
+
+    fn label(&self) -> Text<'_, U8g2TextStyle<Gray4>> {
+        Text::new(&self.label, self.origin, self.label_style.clone())
+    }
+
+    /// Sign cell and digit cells below the label
+    fn value_area(&self) -> Rectangle {
+        let label = self.label().bounding_box();
+        let pitch = self.style.digit_size.width + self.style.digit_spacing;
+        Rectangle::new(
+            Point::new(
+                label.top_left.x,
+                label.top_left.y + label.size.height as i32,
+            ),
+            Size::new(
+                pitch * (self.value.cells.len() as u32 + 1),
+                self.style.digit_size.height,
+            ),
+        )
+    }
+
+    fn draw_digit<D: DrawTarget<Color = Gray4>>(
+        &self,
+        target: &mut D,
+        top_left: Point,
+        segments: u8,
+        dp: bool,
+    ) -> Result<(), D::Error> {
+        let Size {
+            width: w,
+            height: h,
+        } = self.style.digit_size;
+        let t = self.style.segment_width;
+        let (wi, hi, ti) = (w as i32, h as i32, t as i32);
+        // Segments overlap at the corners, so lit ones join into solid strokes
+        let middle = (hi - ti) / 2;
+        let horizontal = Size::new(w, t);
+        let upper = Size::new(t, (h + t) / 2);
+        let lower = Size::new(t, (hi - middle) as u32);
+        let rects = [
+            (SEG_A, Rectangle::new(Point::zero(), horizontal)),
+            (SEG_B, Rectangle::new(Point::new(wi - ti, 0), upper)),
+            (SEG_C, Rectangle::new(Point::new(wi - ti, middle), lower)),
+            (SEG_D, Rectangle::new(Point::new(0, hi - ti), horizontal)),
+            (SEG_E, Rectangle::new(Point::new(0, middle), lower)),
+            (SEG_F, Rectangle::new(Point::zero(), upper)),
+            (SEG_G, Rectangle::new(Point::new(0, middle), horizontal)),
+        ]
+        .map(|(bit, r)| (segments & bit != 0, r.translate(top_left)));
+
+        // Unlit segments first, they would cut the corners of lit ones
+        for active in [false, true] {
+            for (_, rect) in rects.iter().filter(|(a, _)| *a == active) {
+                target.fill_solid(rect, self.color(active))?;
+            }
+        }
+        // Pending value keeps only the outline of the lit segments
+        if self.pending {
+            for (_, rect) in rects.iter().filter(|(a, _)| *a) {
+                target.fill_solid(&rect.offset(-(PENDING_STROKE as i32)), self.color(false))?;
+            }
+        }
+
+        // Centered in the gap after the digit
+        let dp_size = t.min(self.style.digit_spacing);
+        let dp_x = wi + (self.style.digit_spacing as i32 - dp_size as i32) / 2;
+        target.fill_solid(
+            &Rectangle::new(
+                top_left + Point::new(dp_x, hi - dp_size as i32),
+                Size::new(dp_size, dp_size),
+            ),
+            self.color(dp),
+        )
+    }
+
+    fn color(&self, active: bool) -> Gray4 {
+        if active && self.stale {
+            self.style.stale_color
+        } else if active {
+            self.style.color
+        } else {
+            self.style.inactive_color
+        }
+    }
+}
+
+impl View for SevenSegment {
+    fn translate_impl(&mut self, by: Point) {
+        self.origin += by;
+    }
+
+    fn bounds(&self) -> Rectangle {
+        self.label().bounding_box().enveloping(&self.value_area())
+    }
+}
+
+impl<D: DrawTarget<Color = Gray4>> Widget<D> for SevenSegment {
+    /// Returns true if the displayed value, its pending or stale state changed
+    fn refresh(&mut self, state: &AppState, render_time: Duration) -> bool {
+        let new_value = Digits::from_value(
+            self.source.value(state, render_time, self.unit),
+            &self.format,
+            self.value.cells.len(),
+        );
+        let pending = self.source.pending(state);
+        let stale = match (self.max_age, self.source.age(state)) {
+            (Some(max_age), Some(age)) => age > max_age,
+            _ => false,
+        };
+        if new_value == self.value && pending == self.pending && stale == self.stale {
+            return false;
+        }
+
+        log::info!(
+            "Widget change detected: {}, changed to {:?}, pending: {pending}, stale: {stale}",
+            self.label,
+            new_value
+        );
+        self.value = new_value;
+        self.pending = pending;
+        self.stale = stale;
+        true
+    }
+
+    fn draw(&self, target: &mut D, dynamic_only: bool) -> Result<Option<Rectangle>, D::Error> {
+        let area = self.value_area();
+        let pitch = (self.style.digit_size.width + self.style.digit_spacing) as i32;
+
+        // Sign cell only has the middle segment
+        let sign = if self.value.negative { SEG_G } else { 0 };
+        self.draw_digit(target, area.top_left, sign, false)?;
+        for (i, (segments, dp)) in self.value.cells.iter().enumerate() {
+            let top_left = area.top_left + Point::new(pitch * (i as i32 + 1), 0);
+            self.draw_digit(target, top_left, *segments, *dp)?;
+        }
+
+        if dynamic_only {
+            return Ok(Some(area));
+        }
+        let label = self.label();
+        label.draw(target)?;
+        Ok(Some(area.enveloping(&label.bounding_box())))
+    }
+
+    /// Black and white digits take the fastest waveform, dimmed stale ones need grays
+    fn waveform(&self) -> Waveform {
+        if self.stale {
+            Waveform::Gl16
+        } else {
+            Waveform::A2
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cells as text: space is a blank cell, `.` lights the point of the cell before it
+    fn digits(negative: bool, text: &str) -> Digits {
+        let mut cells: Vec<(u8, bool)> = Vec::new();
+        for c in text.chars() {
+            match c {
+                '.' => cells.last_mut().expect("digit before the point").1 = true,
+                c => cells.push((glyph(c), false)),
+            }
+        }
+        Digits { negative, cells }
+    }
+
+    #[test]
+    fn number_fills_cells_from_the_right() {
+        assert_eq!(Digits::number(73.24, 1, 3), digits(false, "73.2"));
+        assert_eq!(Digits::number(5.0, 1, 3), digits(false, " 5.0"));
+        assert_eq!(Digits::number(-12.3, 1, 3), digits(true, "12.3"));
+    }
+
+    #[test]
+    fn decimals_dropped_until_integer_fits() {
+        assert_eq!(Digits::number(123.4, 1, 3), digits(false, "123"));
+        // Rounding up adds a digit
+        assert_eq!(Digits::number(99.96, 1, 3), digits(false, "100"));
+        assert_eq!(Digits::number(9.96, 1, 3), digits(false, "10.0"));
+    }
+
+    #[test]
+    fn overflow_shows_hi_lo() {
+        assert_eq!(Digits::number(1234.0, 1, 3), digits(false, "HI "));
+        assert_eq!(Digits::number(-1234.0, 1, 3), digits(false, "LO "));
+    }
+
+    #[test]
+    fn invalid_and_missing_values() {
+        assert_eq!(Digits::number(f64::NAN, 1, 3), digits(false, "Err"));
+        assert_eq!(Digits::number(f64::INFINITY, 1, 3), digits(false, "Err"));
+        assert_eq!(
+            Digits::from_value(None, &ValueFormat::default(), 3),
+            digits(false, "---")
+        );
+    }
+
+    #[test]
+    fn negative_zero_has_no_sign() {
+        assert_eq!(Digits::number(-0.04, 1, 3), digits(false, " 0.0"));
+        assert_eq!(Digits::number(-0.4, 0, 3), digits(false, "  0"));
+    }
+}
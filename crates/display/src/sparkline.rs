@@ -0,0 +1,192 @@
+use std::time::Duration;
+
+use embedded_graphics::{
+    pixelcolor::Gray4,
+    prelude::*,
+    primitives::{Circle, Line, Polyline, PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+use embedded_layout::View;
+use u8g2_fonts::{fonts, U8g2TextStyle};
+
+use crate::layout::DataSource;
+use crate::state::AppState;
+use crate::units::TemperatureUnit;
+use crate::widget::Widget;
+
+const HEADER_HEIGHT: i32 = 36;
+const FOOTER_HEIGHT: i32 = 20;
+/// Flat series are stretched to at least this many degrees
+const MIN_RANGE: f32 = 1.0;
+
+/// History graph with min/max labels and the setpoint as a horizontal line
+pub struct Sparkline {
+    label: String,
+    size: Size,
+    label_style: U8g2TextStyle<Gray4>,
+    small_style: U8g2TextStyle<Gray4>,
+    source: DataSource,
+    unit: TemperatureUnit,
+    plot: Plot,
+    origin: Point,
+}
+
+/// Everything that ends up on the panel, compared to skip redundant redraws
+#[derive(Default, PartialEq, Debug)]
+struct Plot {
+    points: Vec<Point>,
+    setpoint_y: Option<i32>,
+    min: String,
+    max: String,
+}
+
+impl Sparkline {
+    pub fn new(label: String, size: Size, source: DataSource, unit: TemperatureUnit) -> Sparkline {
+        Sparkline {
+            label,
+            size,
+            label_style: U8g2TextStyle::new(fonts::u8g2_font_spleen16x32_mr, Gray4::BLACK),
+            small_style: U8g2TextStyle::new(fonts::u8g2_font_spleen8x16_mr, Gray4::BLACK),
+            source,
+            unit,
+            plot: Plot::default(),
+            origin: Point::zero(),
+        }
+    }
+
+    fn plot(&self, state: &AppState) -> Plot {
+        let Some(history) = self.source.history(state) else {
+            return Plot::default();
+        };
+
+        let now = state.time_since_boot;
+        let window = history.window().as_secs_f32().max(1.0);
+        let values: Vec<(f32, f32)> = history
+            .ages(now)
+            .map(|(age, value)| (age.as_secs_f32(), self.unit.value(value)))
+            .filter(|(age, _)| *age <= window)
+            .collect();
+        if values.is_empty() {
+            return Plot::default();
+        }
+
+        let (min, max) = values
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(lo, hi), (_, v)| {
+                (lo.min(*v), hi.max(*v))
+            });
+        let setpoint = state.temp_setpoint.map(|t| self.unit.value(t));
+
+        let (mut lo, mut hi) = setpoint.map_or((min, max), |s| (min.min(s), max.max(s)));
+        if hi - lo < MIN_RANGE {
+            let mid = (hi + lo) / 2.0;
+            (lo, hi) = (mid - MIN_RANGE / 2.0, mid + MIN_RANGE / 2.0);
+        }
+
+        let w = self.size.width.saturating_sub(1) as f32;
+        let h = self.size.height.saturating_sub(1) as f32;
+        let y = |v: f32| (h - (v - lo) / (hi - lo) * h).round() as i32;
+
+        let mut points: Vec<Point> = values
+            .iter()
+            .map(|(age, v)| Point::new((w - age / window * w).round() as i32, y(*v)))
+            .collect();
+        points.dedup();
+
+        Plot {
+            points,
+            setpoint_y: setpoint.map(y),
+            min: format!("min {min:.1}"),
+            max: format!("max {max:.1}"),
+        }
+    }
+}
+
+impl View for Sparkline {
+    fn translate_impl(&mut self, by: Point) {
+        self.origin += by;
+    }
+
+    fn bounds(&self) -> Rectangle {
+        Rectangle::new(
+            self.origin,
+            Size::new(
+                self.size.width,
+                (HEADER_HEIGHT + FOOTER_HEIGHT) as u32 + self.size.height,
+            ),
+        )
+    }
+}
+
+impl<D: DrawTarget<Color = Gray4>> Widget<D> for Sparkline {
+    /// Returns true if the plotted pixels changed
+    fn refresh(&mut self, state: &AppState, _render_time: Duration) -> bool {
+        let plot = self.plot(state);
+        if plot == self.plot {
+            return false;
+        }
+
+        log::info!("Widget change detected: {}", self.label);
+        self.plot = plot;
+        true
+    }
+
+    /// Plot has no static part worth keeping, whole widget is redrawn
+    fn draw(&self, target: &mut D, _dynamic_only: bool) -> Result<Option<Rectangle>, D::Error> {
+        let origin = self.origin;
+        let bounds = self.bounds();
+        bounds
+            .into_styled(PrimitiveStyle::with_fill(Gray4::WHITE))
+            .draw(target)?;
+
+        let top_right = TextStyleBuilder::new()
+            .alignment(Alignment::Right)
+            .baseline(Baseline::Top)
+            .build();
+        let right = self.size.width as i32 - 1;
+        let plot_origin = origin + Point::new(0, HEADER_HEIGHT);
+
+        Text::with_baseline(&self.label, origin, self.label_style.clone(), Baseline::Top)
+            .draw(target)?;
+        Text::with_text_style(
+            &self.plot.max,
+            origin + Point::new(right, 0),
+            self.small_style.clone(),
+            top_right,
+        )
+        .draw(target)?;
+        Text::with_text_style(
+            &self.plot.min,
+            plot_origin + Point::new(right, self.size.height as i32 + 2),
+            self.small_style.clone(),
+            top_right,
+        )
+        .draw(target)?;
+
+        Rectangle::new(plot_origin, self.size)
+            .into_styled(PrimitiveStyle::with_stroke(Gray4::new(12), 1))
+            .draw(target)?;
+
+        if let Some(y) = self.plot.setpoint_y {
+            Line::new(
+                plot_origin + Point::new(0, y),
+                plot_origin + Point::new(right, y),
+            )
+            .into_styled(PrimitiveStyle::with_stroke(Gray4::new(8), 1))
+            .draw(target)?;
+        }
+
+        let points: Vec<Point> = self.plot.points.iter().map(|p| *p + plot_origin).collect();
+        match points.as_slice() {
+            [] => {}
+            [p] => Circle::with_center(*p, 4)
+                .into_styled(PrimitiveStyle::with_fill(Gray4::BLACK))
+                .draw(target)?,
+            _ => Polyline::new(&points)
+                .into_styled(PrimitiveStyle::with_stroke(Gray4::BLACK, 2))
+                .draw(target)?,
+        }
+
+        Ok(Some(bounds))
+    }
+}
@@ -1,32 +1,125 @@
 use std::time::Duration;
 
 // use esp_idf_svc::sys::EspError;
+use serde::{ser::SerializeStruct, Serialize, Serializer};
 use uom::si::{
     electric_potential::volt,
     quantities::{ElectricPotential, ThermodynamicTemperature},
-    temperature_interval,
     thermodynamic_temperature::degree_fahrenheit,
 };
 
+use crate::history::History;
+use crate::units::TemperatureSettings;
+
 pub type Voltage = uom::si::f32::ElectricPotential;
 
-#[derive(Clone, Debug)]
+/// 24 hours of sensor readings in 10 minute buckets
+const TEMP_HISTORY_BUCKET: Duration = Duration::from_secs(10 * 60);
+const TEMP_HISTORY_LEN: usize = 144;
+
+/// Bumped when fields of the JSON state are renamed, removed or change meaning
+pub const STATE_SCHEMA_VERSION: u32 = 1;
+
+/// JSON fields carry their unit: `_s` seconds, `_v` volts, `_f` °F regardless of the
+/// configured unit. The temperature history is left out. The README documents the schema.
+#[derive(Clone, Debug, Serialize)]
 pub struct AppState {
     pub updated_counter: u32,
     pub loop_counter: u32,
+    #[serde(rename = "uptime_s", serialize_with = "secs")]
     pub time_since_boot: Duration,
+    #[serde(rename = "batt_voltage_v", serialize_with = "volts")]
     pub batt_voltage: ElectricPotential<f32>,
     pub state_of_charge: f32,
     pub initial_state_of_charge: Option<f32>,
+    /// Uptime of previous boots since `initial_state_of_charge` was measured
+    #[serde(rename = "battery_baseline_span_s", serialize_with = "secs")]
+    pub battery_baseline_span: Duration,
+    /// State of charge drop per hour, negative while charging
     pub state_of_charge_change_rate: Option<f32>,
+    /// No charger status line on the board, inferred from the battery trend
+    pub charging: bool,
+    /// Battery is about to run out, only the low-battery screen is shown
+    pub battery_critical: bool,
     pub network_status: NetworkStatus,
     pub free_heap_bytes: u32,
+    pub active_page: usize,
+    /// Shows diagnostics in the production profile
+    pub show_diagnostics: bool,
+    /// Idle page is shown, set by the display loop after a period without button presses
+    pub idle: bool,
+    pub button_presses: u32,
+    /// Local time of day, `None` until the clock is synced
+    #[serde(rename = "clock_s", serialize_with = "opt_secs")]
+    pub clock: Option<Duration>,
 
-    pub temp_sensor: Option<ThermodynamicTemperature<f32>>,
+    pub temp_sensor: Option<Received<ThermodynamicTemperature<f32>>>,
+    /// Shown setpoint, pending until confirmed on the setpoint command topic
+    #[serde(rename = "temp_setpoint_f", serialize_with = "opt_fahrenheit")]
     pub temp_setpoint: Option<ThermodynamicTemperature<f32>>,
+    /// Last setpoint received on the setpoint command topic
+    pub temp_setpoint_confirmed: Option<Received<ThermodynamicTemperature<f32>>>,
+    pub setpoint_status: SetpointStatus,
+    #[serde(skip)]
+    pub temp_history: History,
+}
+
+/// State as published for remote diagnostics, tagged with the schema version
+#[derive(Serialize)]
+pub struct StateReport<'a> {
+    pub schema: u32,
+    #[serde(flatten)]
+    pub state: &'a AppState,
+}
+
+impl<'a> StateReport<'a> {
+    pub fn new(state: &'a AppState) -> StateReport<'a> {
+        StateReport {
+            schema: STATE_SCHEMA_VERSION,
+            state,
+        }
+    }
 }
 
-#[derive(Clone, Debug)]
+/// Value from outside the device, `at` is the time since boot it arrived at
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Received<T> {
+    pub value: T,
+    pub at: Duration,
+}
+
+impl<T> Received<T> {
+    pub fn age(&self, now: Duration) -> Duration {
+        now.saturating_sub(self.at)
+    }
+}
+
+/// `{ "value_f": 72.5, "received_at_s": 120 }`
+impl Serialize for Received<ThermodynamicTemperature<f32>> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("Received", 2)?;
+        s.serialize_field("value_f", &self.value.get::<degree_fahrenheit>())?;
+        s.serialize_field("received_at_s", &self.at.as_secs())?;
+        s.end()
+    }
+}
+
+/// `{ "state": "pending", "since_s": 120 }`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "state")]
+pub enum SetpointStatus {
+    Confirmed,
+    /// Changed on the device at `since` (time since boot), not confirmed yet
+    Pending {
+        #[serde(rename = "since_s", serialize_with = "secs")]
+        since: Duration,
+    },
+    /// No confirmation in time, reverted to the confirmed setpoint
+    Reverted,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum NetworkStatus {
     Initializing,
     WifiConnected,
@@ -49,30 +142,80 @@ impl AppState {
             batt_voltage: Voltage::new::<volt>(0.0),
             state_of_charge: 0_f32,
             initial_state_of_charge: None,
+            battery_baseline_span: Duration::ZERO,
             state_of_charge_change_rate: None,
+            charging: false,
+            battery_critical: false,
             network_status: NetworkStatus::Initializing,
             free_heap_bytes: 0,
+            active_page: 0,
+            show_diagnostics: false,
+            idle: false,
+            button_presses: 0,
+            clock: None,
             temp_sensor: None,
-            temp_setpoint: Some(temp_f_to_uom(72_f32)),
+            temp_setpoint: Some(uom::si::f32::ThermodynamicTemperature::new::<
+                degree_fahrenheit,
+            >(72_f32)),
+            temp_setpoint_confirmed: None,
+            setpoint_status: SetpointStatus::Confirmed,
+            temp_history: History::new(TEMP_HISTORY_BUCKET, TEMP_HISTORY_LEN),
         }
     }
 
-    pub fn set_temp_sensor_f(&mut self, temp: f32) {
-        self.temp_sensor = Some(temp_f_to_uom(temp));
+    pub fn set_temp_sensor(&mut self, temp: ThermodynamicTemperature<f32>) {
+        self.temp_sensor = Some(self.received(temp));
+        self.temp_history.push(self.time_since_boot, temp);
     }
 
-    pub fn set_temp_setpoint_f(&mut self, temp: f32) {
-        self.temp_setpoint = Some(temp_f_to_uom(temp));
+    /// Setpoint received on the command topic, confirms a pending change or overrides it
+    pub fn set_temp_setpoint(&mut self, temp: ThermodynamicTemperature<f32>) {
+        self.temp_setpoint = Some(temp);
+        self.temp_setpoint_confirmed = Some(self.received(temp));
+        self.setpoint_status = SetpointStatus::Confirmed;
     }
 
-    pub fn adjust_temp_setpoint_f(&mut self, temp: f32) {
-        if let Some(t) = self.temp_setpoint.as_mut() {
-            *t += uom::si::f32::TemperatureInterval::new::<temperature_interval::degree_fahrenheit>(
-                temp,
-            );
+    /// Moves the setpoint by `steps` of `settings.setpoint_step`, within the allowed range.
+    /// The new setpoint stays pending until confirmed.
+    pub fn adjust_temp_setpoint(&mut self, steps: i32, settings: &TemperatureSettings) {
+        if let Some(t) = self.temp_setpoint {
+            self.temp_setpoint = Some(settings.step_setpoint(t, steps));
+            self.setpoint_status = SetpointStatus::Pending {
+                since: self.time_since_boot,
+            };
         };
     }
 
+    /// Reverts a setpoint left unconfirmed for `timeout`, returns true if it did
+    pub fn expire_pending_setpoint(&mut self, timeout: Duration) -> bool {
+        match self.setpoint_status {
+            SetpointStatus::Pending { since } if self.time_since_boot >= since + timeout => {
+                if let Some(confirmed) = self.temp_setpoint_confirmed {
+                    self.temp_setpoint = Some(confirmed.value);
+                }
+                self.setpoint_status = SetpointStatus::Reverted;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn received<T>(&self, value: T) -> Received<T> {
+        Received {
+            value,
+            at: self.time_since_boot,
+        }
+    }
+
+    /// Renderer wraps the index around the number of pages in the layout
+    pub fn next_page(&mut self) {
+        self.active_page = self.active_page.wrapping_add(1);
+    }
+
+    pub fn toggle_diagnostics(&mut self) {
+        self.show_diagnostics = !self.show_diagnostics;
+    }
+
     pub fn refresh_updated_counter(&mut self) {
         self.updated_counter += 1;
     }
@@ -82,6 +225,22 @@ impl AppState {
     }
 }
 
-fn temp_f_to_uom(temp: f32) -> ThermodynamicTemperature<f32> {
-    uom::si::f32::ThermodynamicTemperature::new::<degree_fahrenheit>(temp)
+fn secs<S: Serializer>(d: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(d.as_secs())
+}
+
+fn opt_secs<S: Serializer>(d: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+    d.map(|d| d.as_secs()).serialize(serializer)
+}
+
+fn volts<S: Serializer>(v: &ElectricPotential<f32>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f32(v.get::<volt>())
+}
+
+fn opt_fahrenheit<S: Serializer>(
+    t: &Option<ThermodynamicTemperature<f32>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    t.map(|t| t.get::<degree_fahrenheit>())
+        .serialize(serializer)
 }
@@ -0,0 +1,204 @@
+use std::time::Duration;
+
+use embedded_graphics::{
+    pixelcolor::Gray4,
+    prelude::*,
+    primitives::{Arc, Circle, Line, PrimitiveStyle, Rectangle, Triangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+use embedded_layout::View;
+use u8g2_fonts::{fonts, U8g2TextStyle};
+
+use crate::state::{AppState, NetworkStatus};
+use crate::widget::Widget;
+
+const HEIGHT: u32 = 32;
+const MARGIN: i32 = 8;
+const BATTERY_SIZE: Size = Size::new(36, 18);
+const INACTIVE: Gray4 = Gray4::new(10);
+
+/// Header with network and battery icons
+pub struct StatusBar {
+    width: u32,
+    text_style: U8g2TextStyle<Gray4>,
+    status: Option<Status>,
+    origin: Point,
+}
+
+/// Everything that ends up on the panel, compared to skip redundant redraws
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Status {
+    soc_percent: u8,
+    charging: bool,
+    network: NetworkStatus,
+}
+
+impl StatusBar {
+    pub fn new(width: u32) -> StatusBar {
+        StatusBar {
+            width,
+            text_style: U8g2TextStyle::new(fonts::u8g2_font_spleen8x16_mr, Gray4::BLACK),
+            status: None,
+            origin: Point::zero(),
+        }
+    }
+
+    fn draw_network<T: DrawTarget<Color = Gray4>>(
+        &self,
+        network: NetworkStatus,
+        origin: Point,
+        target: &mut T,
+    ) -> Result<(), T::Error> {
+        let wifi_color = match network {
+            NetworkStatus::WifiConnected | NetworkStatus::MqttConnected => Gray4::BLACK,
+            NetworkStatus::Initializing | NetworkStatus::Error => INACTIVE,
+        };
+
+        // Wifi: dot and three arcs opening upwards
+        let center = origin + Point::new(MARGIN + 12, HEIGHT as i32 - 6);
+        Circle::with_center(center, 5)
+            .into_styled(PrimitiveStyle::with_fill(wifi_color))
+            .draw(target)?;
+        for diameter in [14, 26, 38] {
+            Arc::with_center(
+                center,
+                diameter,
+                Angle::from_degrees(-135.0),
+                Angle::from_degrees(90.0),
+            )
+            .into_styled(PrimitiveStyle::with_stroke(wifi_color, 3))
+            .draw(target)?;
+        }
+
+        if network == NetworkStatus::Error {
+            let cross = PrimitiveStyle::with_stroke(Gray4::BLACK, 3);
+            let tl = origin + Point::new(MARGIN, 4);
+            let br = tl + Point::new(24, 24);
+            Line::new(tl, br).into_styled(cross).draw(target)?;
+            Line::new(Point::new(br.x, tl.y), Point::new(tl.x, br.y))
+                .into_styled(cross)
+                .draw(target)?;
+        }
+
+        let mqtt_color = match network {
+            NetworkStatus::MqttConnected => Gray4::BLACK,
+            _ => INACTIVE,
+        };
+        Text::with_baseline(
+            "MQTT",
+            origin + Point::new(MARGIN + 32, (HEIGHT as i32 - 16) / 2),
+            U8g2TextStyle::new(fonts::u8g2_font_spleen8x16_mr, mqtt_color),
+            Baseline::Top,
+        )
+        .draw(target)?;
+
+        Ok(())
+    }
+
+    fn draw_battery<T: DrawTarget<Color = Gray4>>(
+        &self,
+        status: Status,
+        origin: Point,
+        target: &mut T,
+    ) -> Result<(), T::Error> {
+        let body = Rectangle::new(
+            origin
+                + Point::new(
+                    self.width as i32 - MARGIN - 4 - BATTERY_SIZE.width as i32,
+                    (HEIGHT - BATTERY_SIZE.height) as i32 / 2,
+                ),
+            BATTERY_SIZE,
+        );
+        body.into_styled(PrimitiveStyle::with_stroke(Gray4::BLACK, 2))
+            .draw(target)?;
+        Rectangle::new(
+            body.top_left + Point::new(BATTERY_SIZE.width as i32, 5),
+            Size::new(4, BATTERY_SIZE.height - 10),
+        )
+        .into_styled(PrimitiveStyle::with_fill(Gray4::BLACK))
+        .draw(target)?;
+
+        let inner = body.offset(-4);
+        let fill_width = (inner.size.width * status.soc_percent as u32) / 100;
+        Rectangle::new(inner.top_left, Size::new(fill_width, inner.size.height))
+            .into_styled(PrimitiveStyle::with_fill(Gray4::BLACK))
+            .draw(target)?;
+
+        let mut text_right = body.top_left.x - 6;
+        if status.charging {
+            // Lightning bolt left of the battery
+            let o = Point::new(body.top_left.x - 16, origin.y + 6);
+            let bolt = PrimitiveStyle::with_fill(Gray4::BLACK);
+            Triangle::new(
+                o + Point::new(7, 0),
+                o + Point::new(0, 11),
+                o + Point::new(6, 11),
+            )
+            .into_styled(bolt)
+            .draw(target)?;
+            Triangle::new(
+                o + Point::new(4, 9),
+                o + Point::new(10, 9),
+                o + Point::new(3, 20),
+            )
+            .into_styled(bolt)
+            .draw(target)?;
+            text_right -= 16;
+        }
+
+        Text::with_text_style(
+            &format!("{}%", status.soc_percent),
+            Point::new(text_right, origin.y + (HEIGHT as i32 - 16) / 2),
+            self.text_style.clone(),
+            TextStyleBuilder::new()
+                .alignment(Alignment::Right)
+                .baseline(Baseline::Top)
+                .build(),
+        )
+        .draw(target)?;
+
+        Ok(())
+    }
+}
+
+impl View for StatusBar {
+    fn translate_impl(&mut self, by: Point) {
+        self.origin += by;
+    }
+
+    fn bounds(&self) -> Rectangle {
+        Rectangle::new(self.origin, Size::new(self.width, HEIGHT))
+    }
+}
+
+impl<D: DrawTarget<Color = Gray4>> Widget<D> for StatusBar {
+    /// Returns true if any icon changed
+    fn refresh(&mut self, state: &AppState, _render_time: Duration) -> bool {
+        let status = Status {
+            soc_percent: (state.state_of_charge.clamp(0.0, 1.0) * 100.0).round() as u8,
+            charging: state.charging,
+            network: state.network_status,
+        };
+        if self.status == Some(status) {
+            return false;
+        }
+
+        log::info!("Status bar change detected: {status:?}");
+        self.status = Some(status);
+        true
+    }
+
+    fn draw(&self, target: &mut D, _dynamic_only: bool) -> Result<Option<Rectangle>, D::Error> {
+        let bounds = self.bounds();
+        bounds
+            .into_styled(PrimitiveStyle::with_fill(Gray4::WHITE))
+            .draw(target)?;
+
+        if let Some(status) = self.status {
+            self.draw_network(status.network, self.origin, target)?;
+            self.draw_battery(status, self.origin, target)?;
+        }
+
+        Ok(Some(bounds))
+    }
+}
@@ -1,108 +1,150 @@
-use std::cmp::max;
-
 use embedded_graphics::{
     prelude::{DrawTarget, PixelColor, Point, Size},
     primitives::Rectangle,
 };
-use u8g2_fonts::{fonts, types::FontColor, FontRenderer, LookupError};
+use u8g2_fonts::{
+    fonts,
+    types::{FontColor, HorizontalAlignment, VerticalPosition},
+    FontRenderer,
+};
 
-use crate::util::{log_font_err, merge, RectExt2};
+use crate::layout::{Align, SourceValue, TableLayout, ValueFormat};
+use crate::util::{log_font_err, RectExt2};
 
+/// Rows of label, value and unit. Columns are as wide as their widest text,
+/// the value column only grows so values don't jump around while updating.
 pub(crate) struct DisplayTable<T, Color>
 where
     Color: PixelColor,
 {
     font: FontRenderer,
-    text_offset: Point,
-    item_bounding_box: Rectangle,
+    style: TableLayout,
+    row_height: u32,
+    /// Width of the label, value and unit columns
+    widths: [u32; 3],
+    /// Value column grew, everything is redrawn on the next draw
+    resized: bool,
     pub items: Vec<DisplayTableItem<T>>,
     color_fg: Color,
     color_bg: Color,
 }
 
+type ValueFn<T> = Box<dyn Fn(&T) -> Option<SourceValue>>;
+
+/// Row reads a typed value from `T`, the table formats it with the row's `format`
 pub(crate) struct DisplayTableItem<T> {
-    name: &'static str,
+    name: String,
+    format: ValueFormat,
     last_value: Option<String>,
     changed: bool,
     pub bounds: Rectangle,
-    value_fn: fn(&T) -> String,
+    value_fn: ValueFn<T>,
 }
 
 impl<T, C> DisplayTable<T, C>
 where
     C: PixelColor,
 {
-    pub fn new(fg: C, bg: C) -> Result<DisplayTable<T, C>, LookupError> {
+    pub fn new(fg: C, bg: C, style: TableLayout) -> DisplayTable<T, C> {
         let font =
             FontRenderer::new::<fonts::u8g2_font_spleen16x32_mr>().with_ignore_unknown_chars(true);
 
-        let title_bb = font
-            .get_rendered_dimensions(
-                "SOC d-rate: ",
-                Point::zero(),
-                u8g2_fonts::types::VerticalPosition::Top,
-            )?
-            .bounding_box
-            .expect("Failed to get rendered dimensions");
-
-        let text_bb = font
-            .get_rendered_dimensions(
-                "0.0000 1/hr",
-                Point::zero(),
-                u8g2_fonts::types::VerticalPosition::Top,
-            )?
-            .bounding_box
-            .expect("Failed to get rendered dimensions");
-
-        Ok(DisplayTable {
+        DisplayTable {
+            row_height: font.get_default_line_height(),
             font,
-            item_bounding_box: Rectangle::new(
-                Point::zero(),
-                Size::new(
-                    title_bb.size.width + text_bb.size.width,
-                    max(title_bb.size.height, text_bb.size.height),
-                ),
-            ),
-            text_offset: Point::new(title_bb.size.width as i32, 0),
+            style,
+            widths: [0; 3],
+            resized: false,
             items: vec![],
             color_fg: fg,
             color_bg: bg,
-        })
+        }
     }
 
-    pub fn add_item(&mut self, name: &'static str, value_fn: fn(&T) -> String) {
-        let i = DisplayTableItem {
+    pub fn add_item(&mut self, name: String, format: ValueFormat, value_fn: ValueFn<T>) {
+        self.widths[0] = self.widths[0].max(self.text_width(&name));
+        if let Some(unit) = &format.unit {
+            self.widths[2] = self.widths[2].max(self.text_width(unit));
+        }
+
+        self.items.push(DisplayTableItem {
             name,
+            format,
             last_value: None,
-            bounds: self.item_bounding_box,
+            bounds: Rectangle::new(Point::zero(), Size::new(0, self.row_height)),
             value_fn,
             changed: false,
-        };
-        self.items.push(i);
+        });
+        self.resize_items();
+    }
+
+    fn text_width(&self, text: &str) -> u32 {
+        self.font
+            .get_rendered_dimensions(text, Point::zero(), VerticalPosition::Top)
+            .map(|d| d.advance.x.max(0) as u32)
+            .unwrap_or(0)
+    }
+
+    fn resize_items(&mut self) {
+        let gap = self.style.column_gap;
+        let width = self.widths.iter().sum::<u32>() + 2 * gap;
+        for i in &mut self.items {
+            i.bounds.size.width = width;
+        }
+    }
+
+    /// Left edge and width of a column, relative to the row
+    fn column(&self, idx: usize) -> (i32, u32) {
+        let x = self.widths[..idx]
+            .iter()
+            .map(|w| w + self.style.column_gap)
+            .sum::<u32>();
+        (x as i32, self.widths[idx])
+    }
+
+    /// Anchor point and alignment of `text` in a column
+    fn anchor(&self, row: &Rectangle, idx: usize, align: Align) -> (Point, HorizontalAlignment) {
+        let (x, width) = self.column(idx);
+        match align {
+            Align::Left => (row.top_left + Point::new(x, 0), HorizontalAlignment::Left),
+            Align::Right => (
+                row.top_left + Point::new(x + width as i32, 0),
+                HorizontalAlignment::Right,
+            ),
+        }
     }
 
     pub fn draw<D>(
         &mut self,
         dynamic_only: bool,
         target: &mut D,
-    ) -> Result<Option<Rectangle>, D::Error>
+    ) -> Result<Vec<Rectangle>, D::Error>
     where
         D: DrawTarget<Color = C>,
     {
-        let mut bb = None;
+        let dynamic_only = dynamic_only && !std::mem::take(&mut self.resized);
+        let mut dirty = Vec::new();
 
         if !dynamic_only {
             log::info!("Rendering labels");
-            bb.merge(&self.draw_labels(target).or_else(log_font_err)?);
+            let mut area: Option<Rectangle> = None;
+            for i in &self.items {
+                area.merge_rect(i.bounds);
+            }
+            if let Some(area) = area {
+                target.fill_solid(&area, self.color_bg)?;
+                dirty.push(area);
+            }
+            dirty.extend(self.draw_labels(target).or_else(log_font_err)?);
         }
-        bb.merge(
-            &self
-                .draw_values(dynamic_only, target)
+        dirty.extend(
+            self.draw_values(dynamic_only, target)
                 .or_else(log_font_err)?,
         );
-        Ok(bb)
+        Ok(dirty)
     }
 
+    /// Labels and units, they don't change after the table is built
     pub fn draw_labels<Display, DisplayError>(
         &self,
         display: &mut Display,
@@ -111,21 +153,34 @@ where
         Display: DrawTarget<Color = C, Error = DisplayError>,
     {
         let mut bb: Option<Rectangle> = None;
-        let font = &self.font;
+        let color = FontColor::WithBackground {
+            fg: self.color_fg,
+            bg: self.color_bg,
+        };
         for i in &self.items {
-            let text_box = font.render_aligned(
-                i.name,
-                i.bounds.top_left,
-                u8g2_fonts::types::VerticalPosition::Top,
-                u8g2_fonts::types::HorizontalAlignment::Left,
-                FontColor::WithBackground {
-                    fg: self.color_fg,
-                    bg: self.color_bg,
-                },
+            let (at, align) = self.anchor(&i.bounds, 0, self.style.label_align);
+            let text_box = self.font.render_aligned(
+                i.name.as_str(),
+                at,
+                VerticalPosition::Top,
+                align,
+                color,
                 display,
             )?;
-
             bb.merge(&text_box);
+
+            if let Some(unit) = &i.format.unit {
+                let (at, align) = self.anchor(&i.bounds, 2, self.style.unit_align);
+                let text_box = self.font.render_aligned(
+                    unit.as_str(),
+                    at,
+                    VerticalPosition::Top,
+                    align,
+                    color,
+                    display,
+                )?;
+                bb.merge(&text_box);
+            }
         }
 
         Ok(bb)
@@ -135,46 +190,69 @@ where
         &mut self,
         dynamic_only: bool,
         display: &mut Display,
-    ) -> Result<Option<Rectangle>, u8g2_fonts::Error<Display::Error>>
+    ) -> Result<Vec<Rectangle>, u8g2_fonts::Error<Display::Error>>
     where
         Display: DrawTarget<Color = C, Error = DisplayError>,
     {
-        let mut bb: Option<Rectangle> = None;
-        let font = &self.font;
-        for i in &mut self.items {
-            if !dynamic_only || i.changed {
-                if let Some(v) = i.last_value.as_ref() {
-                    let text_box = font.render_aligned(
-                        v.as_str(),
-                        Point::new(
-                            i.bounds.top_left.x + self.text_offset.x,
-                            i.bounds.top_left.y + self.text_offset.y,
-                        ),
-                        u8g2_fonts::types::VerticalPosition::Top,
-                        u8g2_fonts::types::HorizontalAlignment::Left,
-                        FontColor::WithBackground {
-                            fg: self.color_fg,
-                            bg: self.color_bg,
-                        },
-                        display,
-                    )?;
-                    i.changed = false;
-                    bb = merge(bb, text_box);
-                }
+        let mut dirty = Vec::new();
+        let (x, width) = self.column(1);
+        for idx in 0..self.items.len() {
+            let i = &self.items[idx];
+            if dynamic_only && !i.changed {
+                continue;
             }
+            let Some(v) = i.last_value.as_ref() else {
+                continue;
+            };
+
+            // Shorter values must not leave the tail of the previous one behind
+            let cell = Rectangle::new(
+                i.bounds.top_left + Point::new(x, 0),
+                Size::new(width, self.row_height),
+            );
+            display
+                .fill_solid(&cell, self.color_bg)
+                .map_err(u8g2_fonts::Error::DisplayError)?;
+
+            let (at, align) = self.anchor(&i.bounds, 1, self.style.value_align);
+            let text_box = self.font.render_aligned(
+                v.as_str(),
+                at,
+                VerticalPosition::Top,
+                align,
+                FontColor::WithBackground {
+                    fg: self.color_fg,
+                    bg: self.color_bg,
+                },
+                display,
+            )?;
+            let mut area = Some(cell);
+            area.merge(&text_box);
+            dirty.extend(area);
+            self.items[idx].changed = false;
         }
 
-        Ok(bb)
+        Ok(dirty)
     }
 
     pub fn update(&mut self, state: &T) {
-        for i in &mut self.items {
-            let new_value = (i.value_fn)(state);
-            if i.last_value.as_ref() != Some(&new_value) {
+        let mut value_width = self.widths[1];
+        for idx in 0..self.items.len() {
+            let i = &self.items[idx];
+            let new_value = i.format.text((i.value_fn)(state));
+            if self.items[idx].last_value.as_ref() != Some(&new_value) {
+                value_width = value_width.max(self.text_width(&new_value));
+                let i = &mut self.items[idx];
+                log::info!("Change detected in {}", i.name);
                 i.last_value = Some(new_value);
                 i.changed = true;
-                log::info!("Change detected in {}", i.name);
             }
         }
+
+        if value_width > self.widths[1] {
+            self.widths[1] = value_width;
+            self.resized = true;
+            self.resize_items();
+        }
     }
 }
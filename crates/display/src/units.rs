@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use thiserror::Error;
+use uom::si::{
+    f32::{TemperatureInterval, ThermodynamicTemperature},
+    temperature_interval, thermodynamic_temperature,
+};
+
+/// Unit temperatures are shown, entered and published in.
+/// Quantities are kept as `uom` values everywhere else.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TemperatureUnit {
+    Celsius,
+    #[default]
+    Fahrenheit,
+}
+
+impl TemperatureUnit {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "°C",
+            TemperatureUnit::Fahrenheit => "°F",
+        }
+    }
+
+    pub fn value(&self, temp: ThermodynamicTemperature) -> f32 {
+        match self {
+            TemperatureUnit::Celsius => temp.get::<thermodynamic_temperature::degree_celsius>(),
+            TemperatureUnit::Fahrenheit => {
+                temp.get::<thermodynamic_temperature::degree_fahrenheit>()
+            }
+        }
+    }
+
+    pub fn temperature(&self, value: f32) -> ThermodynamicTemperature {
+        match self {
+            TemperatureUnit::Celsius => {
+                ThermodynamicTemperature::new::<thermodynamic_temperature::degree_celsius>(value)
+            }
+            TemperatureUnit::Fahrenheit => {
+                ThermodynamicTemperature::new::<thermodynamic_temperature::degree_fahrenheit>(value)
+            }
+        }
+    }
+
+    pub fn interval(&self, value: f32) -> TemperatureInterval {
+        match self {
+            TemperatureUnit::Celsius => {
+                TemperatureInterval::new::<temperature_interval::degree_celsius>(value)
+            }
+            TemperatureUnit::Fahrenheit => {
+                TemperatureInterval::new::<temperature_interval::degree_fahrenheit>(value)
+            }
+        }
+    }
+}
+
+/// Setpoint policy shared by the buttons, incoming MQTT commands and the
+/// Home Assistant discovery payload. Values are in `unit`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TemperatureSettings {
+    pub unit: TemperatureUnit,
+    /// Unit the sensor topic is published in, set by its source rather than this screen
+    pub sensor_unit: TemperatureUnit,
+    /// Setpoint change per button press, setpoints are kept on multiples of it
+    pub setpoint_step: f32,
+    /// 32 °F when not set
+    pub setpoint_min: Option<f32>,
+    /// 90 °F when not set
+    pub setpoint_max: Option<f32>,
+    /// Setpoint changed on the device reverts unless confirmed within it, 0 waits forever
+    pub setpoint_confirm_secs: u64,
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum SetpointError {
+    #[error("setpoint {value} is outside {min}..={max}")]
+    OutOfRange { value: f32, min: f32, max: f32 },
+    #[error("setpoint is not a number")]
+    NotANumber,
+}
+
+impl Default for TemperatureSettings {
+    fn default() -> Self {
+        TemperatureSettings {
+            unit: TemperatureUnit::Fahrenheit,
+            sensor_unit: TemperatureUnit::Fahrenheit,
+            setpoint_step: 0.5,
+            setpoint_min: None,
+            setpoint_max: None,
+            setpoint_confirm_secs: 60,
+        }
+    }
+}
+
+impl TemperatureSettings {
+    /// Accepted setpoints in `unit`, rounded to 0.1
+    pub fn setpoint_range(&self) -> (f32, f32) {
+        let bound = |f: f32| {
+            let t = TemperatureUnit::Fahrenheit.temperature(f);
+            (self.unit.value(t) * 10.0).round() / 10.0
+        };
+        (
+            self.setpoint_min.unwrap_or_else(|| bound(32.0)),
+            self.setpoint_max.unwrap_or_else(|| bound(90.0)),
+        )
+    }
+
+    pub fn setpoint_confirm_timeout(&self) -> Option<Duration> {
+        (self.setpoint_confirm_secs > 0).then(|| Duration::from_secs(self.setpoint_confirm_secs))
+    }
+
+    /// Validates a setpoint received in `unit`, out of range values are rejected
+    pub fn setpoint(&self, value: f32) -> Result<ThermodynamicTemperature, SetpointError> {
+        if value.is_nan() {
+            return Err(SetpointError::NotANumber);
+        }
+        let (min, max) = self.setpoint_range();
+        if !(min..=max).contains(&value) {
+            return Err(SetpointError::OutOfRange { value, min, max });
+        }
+        Ok(self.unit.temperature(value))
+    }
+
+    /// Setpoint moved by `steps` button presses, snapped to the step and clamped to the range
+    pub fn step_setpoint(
+        &self,
+        setpoint: ThermodynamicTemperature,
+        steps: i32,
+    ) -> ThermodynamicTemperature {
+        let (min, max) = self.setpoint_range();
+        let mut value = self.unit.value(setpoint);
+        if self.setpoint_step > 0.0 {
+            value = ((value / self.setpoint_step).round() + steps as f32) * self.setpoint_step;
+        }
+        self.unit.temperature(value.clamp(min, max))
+    }
+}
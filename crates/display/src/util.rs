@@ -1,14 +1,30 @@
-use embedded_graphics::primitives::Rectangle;
+use embedded_graphics::{prelude::Size, primitives::Rectangle};
 use embedded_layout::prelude::RectExt;
 use log::warn;
 
-pub(crate) fn merge(r1: Option<Rectangle>, r2: Option<Rectangle>) -> Option<Rectangle> {
-    match (r1, r2) {
-        (Some(r1), Some(r2)) => Some(r1.enveloping(&r2)),
-        (Some(r1), None) => Some(r1),
-        (None, Some(r2)) => Some(r2),
-        (None, None) => None,
+use crate::refresh::DirtyRegion;
+
+/// Merges regions that overlap or lie within `gap` pixels of each other.
+/// Far apart regions stay separate, so they can be refreshed independently.
+/// Merged region keeps the cleanest of the waveforms.
+pub(crate) fn coalesce(regions: Vec<DirtyRegion>, gap: u32) -> Vec<DirtyRegion> {
+    let mut result: Vec<DirtyRegion> = Vec::with_capacity(regions.len());
+
+    for mut r in regions {
+        // Growing `r` may bring it close to regions it previously missed
+        while let Some(idx) = result.iter().position(|o| is_near(&o.area, &r.area, gap)) {
+            let other = result.swap_remove(idx);
+            r.area = r.area.enveloping(&other.area);
+            r.waveform = r.waveform.max(other.waveform);
+        }
+        result.push(r);
     }
+
+    result
+}
+
+fn is_near(r1: &Rectangle, r2: &Rectangle, gap: u32) -> bool {
+    r1.offset(gap as i32).intersection(r2).size != Size::zero()
 }
 
 pub(crate) trait RectExt2 {
@@ -36,18 +52,75 @@ impl RectExt2 for Option<Rectangle> {
     }
 }
 
-pub(crate) fn log_font_err<DisplayError>(
+pub(crate) fn log_font_err<T: Default, DisplayError>(
     err: u8g2_fonts::Error<DisplayError>,
-) -> Result<Option<Rectangle>, DisplayError> {
+) -> Result<T, DisplayError> {
     match err {
         u8g2_fonts::Error::BackgroundColorNotSupported => {
             warn!("Background color not supported");
-            Ok(None)
+            Ok(T::default())
         }
         u8g2_fonts::Error::GlyphNotFound(g) => {
             warn!("Glyph not found {}", g);
-            Ok(None)
+            Ok(T::default())
         }
         u8g2_fonts::Error::DisplayError(e) => Err(e),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::prelude::Point;
+
+    use super::*;
+    use crate::refresh::Waveform;
+
+    fn region(x: i32, y: i32, w: u32, h: u32, waveform: Waveform) -> DirtyRegion {
+        DirtyRegion::new(Rectangle::new(Point::new(x, y), Size::new(w, h)), waveform)
+    }
+
+    #[test]
+    fn overlapping_regions_merged() {
+        let merged = coalesce(
+            vec![
+                region(0, 0, 20, 20, Waveform::A2),
+                region(10, 10, 20, 20, Waveform::Gl16),
+            ],
+            0,
+        );
+        assert_eq!(merged, [region(0, 0, 30, 30, Waveform::Gl16)]);
+    }
+
+    #[test]
+    fn adjacent_regions_merged_within_gap() {
+        let regions = vec![
+            region(0, 0, 10, 10, Waveform::A2),
+            region(10, 0, 10, 10, Waveform::A2),
+        ];
+        assert_eq!(coalesce(regions.clone(), 0), regions);
+        assert_eq!(coalesce(regions, 1), [region(0, 0, 20, 10, Waveform::A2)]);
+    }
+
+    #[test]
+    fn far_regions_kept_apart() {
+        let regions = vec![
+            region(0, 0, 10, 10, Waveform::A2),
+            region(100, 100, 10, 10, Waveform::Gc16),
+        ];
+        assert_eq!(coalesce(regions.clone(), 8), regions);
+    }
+
+    #[test]
+    fn grown_region_picks_up_earlier_ones() {
+        // The third region bridges the first two
+        let merged = coalesce(
+            vec![
+                region(0, 0, 10, 10, Waveform::A2),
+                region(40, 0, 10, 10, Waveform::Gl16),
+                region(5, 0, 40, 10, Waveform::A2),
+            ],
+            0,
+        );
+        assert_eq!(merged, [region(0, 0, 50, 10, Waveform::Gl16)]);
+    }
+}
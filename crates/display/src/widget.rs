@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+use embedded_graphics::{pixelcolor::Gray4, prelude::*, primitives::Rectangle};
+use embedded_layout::View;
+
+use crate::refresh::Waveform;
+use crate::state::AppState;
+
+/// Anything the renderer can place on a page and keep up to date.
+///
+/// Layout moves widgets through `View::translate_impl`, `View::bounds` must
+/// follow. Implement it for every target to use a widget on the panel, in
+/// the simulator and in snapshot tests alike:
+///
+/// ```ignore
+/// impl<D: DrawTarget<Color = Gray4>> Widget<D> for Gauge { ... }
+/// renderer.add_widget("thermostat", Position { row: 2, column: 0 }, Box::new(Gauge::new()))?;
+/// ```
+pub trait Widget<D: DrawTarget<Color = Gray4>>: View {
+    /// Reads new values from `state`, returns true if the widget needs a redraw
+    fn refresh(&mut self, state: &AppState, render_time: Duration) -> bool;
+
+    /// Draws the widget and returns the area it touched. With `dynamic_only`
+    /// static parts like labels may be skipped, they are still on the panel.
+    fn draw(&self, target: &mut D, dynamic_only: bool) -> Result<Option<Rectangle>, D::Error>;
+
+    /// Waveform for partial refreshes of this widget
+    fn waveform(&self) -> Waveform {
+        Waveform::Gl16
+    }
+}
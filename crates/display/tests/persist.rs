@@ -0,0 +1,111 @@
+//! State saved to NVS before a reboot and restored after it.
+
+use std::time::Duration;
+
+use display::{
+    buttons::Button,
+    persist::{PersistError, Persisted},
+    state::{AppState, SetpointStatus},
+    units::{TemperatureSettings, TemperatureUnit},
+};
+use uom::si::{f32::ThermodynamicTemperature, thermodynamic_temperature::degree_fahrenheit};
+
+fn fahrenheit(v: f32) -> ThermodynamicTemperature {
+    ThermodynamicTemperature::new::<degree_fahrenheit>(v)
+}
+
+fn saved_state() -> AppState {
+    let mut state = AppState {
+        initial_state_of_charge: Some(0.98),
+        active_page: 2,
+        ..AppState::new()
+    };
+    for minutes in [0, 20, 40] {
+        state.time_since_boot = Duration::from_secs(minutes * 60);
+        state.set_temp_sensor(fahrenheit(70.0 + minutes as f32 / 10.0));
+    }
+    state.set_temp_setpoint(fahrenheit(71.5));
+    state
+}
+
+#[test]
+fn state_survives_reboot() {
+    let temperature = TemperatureSettings::default();
+    let saved = Persisted::from_state(&saved_state(), temperature.unit);
+    let bytes = saved.encode();
+    assert_eq!(Persisted::decode(&bytes), Ok(saved.clone()));
+
+    let mut booted = AppState::new();
+    saved.apply(&mut booted, &temperature);
+    let setpoint = booted.temp_setpoint.expect("setpoint restored");
+    assert!((setpoint.get::<degree_fahrenheit>() - 71.5).abs() < 0.01);
+    assert_eq!(
+        booted.temp_setpoint_confirmed.map(|c| c.value),
+        Some(setpoint)
+    );
+    assert_eq!(booted.active_page, 2);
+    assert_eq!(booted.initial_state_of_charge, Some(0.98));
+    assert_eq!(booted.battery_baseline_span, Duration::from_secs(40 * 60));
+
+    // Readings keep their age, new ones follow them
+    let ages: Vec<_> = booted
+        .temp_history
+        .ages(Duration::ZERO)
+        .map(|(age, _)| age.as_secs())
+        .collect();
+    assert_eq!(ages, [2400, 1200, 0]);
+    booted.time_since_boot = Duration::from_secs(20 * 60);
+    booted.set_temp_sensor(fahrenheit(75.0));
+    assert_eq!(booted.temp_history.ages(booted.time_since_boot).count(), 4);
+}
+
+#[test]
+fn setpoint_fitted_to_current_policy() {
+    let mut state = saved_state();
+    state.set_temp_setpoint(fahrenheit(89.9));
+    let saved = Persisted::from_state(&state, TemperatureUnit::Fahrenheit);
+
+    let celsius = TemperatureSettings {
+        unit: TemperatureUnit::Celsius,
+        setpoint_max: Some(30.0),
+        ..TemperatureSettings::default()
+    };
+    let mut booted = AppState::new();
+    saved.apply(&mut booted, &celsius);
+    let setpoint = celsius
+        .unit
+        .value(booted.temp_setpoint.expect("setpoint restored"));
+    assert!((setpoint - 30.0).abs() < 0.01);
+}
+
+#[test]
+fn invalid_state_rejected() {
+    let bytes = Persisted::from_state(&saved_state(), TemperatureUnit::Fahrenheit).encode();
+    assert_eq!(
+        Persisted::decode(&bytes[..bytes.len() - 1]),
+        Err(PersistError::Truncated)
+    );
+    assert_eq!(Persisted::decode(&[]), Err(PersistError::Truncated));
+
+    let mut future = bytes.clone();
+    future[0] = 99;
+    assert_eq!(Persisted::decode(&future), Err(PersistError::Version(99)));
+}
+
+#[test]
+fn unconfirmed_press_reverts_to_restored_setpoint() {
+    let temperature = TemperatureSettings::default();
+    let saved = Persisted::from_state(&saved_state(), temperature.unit);
+    let mut booted = AppState::new();
+    saved.apply(&mut booted, &temperature);
+
+    let timeout = temperature
+        .setpoint_confirm_timeout()
+        .expect("timeout is set");
+    Button::Up.press(&mut booted, &temperature);
+    booted.time_since_boot += timeout;
+    assert!(booted.expire_pending_setpoint(timeout));
+    assert_eq!(booted.setpoint_status, SetpointStatus::Reverted);
+    let setpoint = booted.temp_setpoint.expect("setpoint restored");
+    assert!((setpoint.get::<degree_fahrenheit>() - 71.5).abs() < 0.01);
+}
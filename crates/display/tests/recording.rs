@@ -0,0 +1,54 @@
+//! Round trip of `AppState` records between the firmware log and the simulator replay.
+
+use std::time::Duration;
+
+use display::{
+    recording::{self, RecordError},
+    state::{AppState, NetworkStatus, Voltage},
+};
+use uom::si::{
+    electric_potential::volt, f32::ThermodynamicTemperature,
+    thermodynamic_temperature::degree_fahrenheit,
+};
+
+fn recorded_state() -> AppState {
+    let mut state = AppState {
+        loop_counter: 2460,
+        time_since_boot: Duration::from_secs(73849),
+        batt_voltage: Voltage::new::<volt>(4.014_f32),
+        state_of_charge: 0.79,
+        initial_state_of_charge: Some(0.98),
+        network_status: NetworkStatus::WifiConnected,
+        free_heap_bytes: 189000,
+        active_page: 2,
+        ..AppState::new()
+    };
+    state.set_temp_sensor(ThermodynamicTemperature::new::<degree_fahrenheit>(73.2_f32));
+    state
+}
+
+#[test]
+fn record_roundtrip() {
+    let line = recording::encode(&recorded_state());
+    let mut replayed = AppState::new();
+    recording::apply(&format!("I (73849) app::ui: {line}"), &mut replayed).expect("Invalid record");
+
+    assert_eq!(recording::encode(&replayed), line);
+    assert_eq!(replayed.state_of_charge_change_rate, None);
+    assert_eq!(replayed.temp_history.samples().count(), 1);
+}
+
+#[test]
+fn invalid_record_leaves_state_untouched() {
+    let line = recording::encode(&recorded_state()).replace(",W,", ",X,");
+    let mut state = AppState::new();
+    assert_eq!(
+        recording::apply(&line, &mut state),
+        Err(RecordError::Invalid("network status", "X".to_owned()))
+    );
+    assert_eq!(state.network_status, NetworkStatus::Initializing);
+    assert_eq!(
+        recording::apply("no record here", &mut state),
+        Err(RecordError::NoRecord)
+    );
+}
@@ -0,0 +1,113 @@
+//! Setpoint policy: buttons stay within the range, out-of-range commands are rejected,
+//! unconfirmed changes revert.
+
+use std::time::Duration;
+
+use display::{
+    buttons::Button,
+    layout::{LayoutError, ScreenLayout},
+    state::{AppState, SetpointStatus},
+    units::{SetpointError, TemperatureSettings, TemperatureUnit},
+};
+use uom::si::{f32::ThermodynamicTemperature, thermodynamic_temperature::degree_fahrenheit};
+
+fn setpoint_f(state: &AppState) -> f32 {
+    state
+        .temp_setpoint
+        .expect("setpoint is set")
+        .get::<degree_fahrenheit>()
+}
+
+#[test]
+fn buttons_stop_at_range() {
+    let settings = TemperatureSettings::default();
+    let mut state = AppState::new();
+    state.set_temp_setpoint(ThermodynamicTemperature::new::<degree_fahrenheit>(89.2_f32));
+
+    // Snapped to the step first
+    Button::Up.press(&mut state, &settings);
+    assert!((setpoint_f(&state) - 89.5).abs() < 0.01);
+    for _ in 0..5 {
+        Button::Up.press(&mut state, &settings);
+    }
+    assert!((setpoint_f(&state) - 90.0).abs() < 0.01);
+
+    Button::Down.press(&mut state, &settings);
+    assert!((setpoint_f(&state) - 89.5).abs() < 0.01);
+}
+
+#[test]
+fn out_of_range_command_rejected() {
+    let settings = TemperatureSettings {
+        unit: TemperatureUnit::Celsius,
+        setpoint_min: Some(10.0),
+        ..TemperatureSettings::default()
+    };
+    assert_eq!(settings.setpoint_range(), (10.0, 32.2));
+    assert!(settings.setpoint(21.5).is_ok());
+    assert_eq!(
+        settings.setpoint(35.0),
+        Err(SetpointError::OutOfRange {
+            value: 35.0,
+            min: 10.0,
+            max: 32.2
+        })
+    );
+    assert_eq!(settings.setpoint(f32::NAN), Err(SetpointError::NotANumber));
+}
+
+#[test]
+fn invalid_policy_rejected() {
+    let layout = |temperature: &str| {
+        ScreenLayout::from_toml(&format!(
+            "[temperature]\n{temperature}\n[[pages]]\nname = \"main\"\n"
+        ))
+    };
+    assert!(layout("setpoint_min = 60.0").is_ok());
+    assert!(matches!(
+        layout("setpoint_min = 95.0"),
+        Err(LayoutError::SetpointRange { min, max }) if min == 95.0 && max == 90.0
+    ));
+    // Default maximum is 32.2 °C
+    assert!(matches!(
+        layout("unit = \"celsius\"\nsetpoint_min = 60.0"),
+        Err(LayoutError::SetpointRange { .. })
+    ));
+    assert!(matches!(
+        layout("setpoint_max = nan"),
+        Err(LayoutError::SetpointRange { .. })
+    ));
+    assert!(matches!(
+        layout("setpoint_step = 0.0"),
+        Err(LayoutError::SetpointStep(_))
+    ));
+}
+
+#[test]
+fn unconfirmed_setpoint_reverts() {
+    let settings = TemperatureSettings::default();
+    let timeout = settings.setpoint_confirm_timeout().expect("timeout is set");
+    let mut state = AppState::new();
+    state.set_temp_setpoint(ThermodynamicTemperature::new::<degree_fahrenheit>(72.0_f32));
+
+    state.time_since_boot = Duration::from_secs(100);
+    Button::Up.press(&mut state, &settings);
+    assert_eq!(
+        state.setpoint_status,
+        SetpointStatus::Pending {
+            since: Duration::from_secs(100)
+        }
+    );
+    assert!(!state.expire_pending_setpoint(timeout));
+
+    state.time_since_boot += timeout;
+    assert!(state.expire_pending_setpoint(timeout));
+    assert_eq!(state.setpoint_status, SetpointStatus::Reverted);
+    assert!((setpoint_f(&state) - 72.0).abs() < 0.01);
+
+    // A received value confirms whatever is shown
+    Button::Down.press(&mut state, &settings);
+    state.set_temp_setpoint(ThermodynamicTemperature::new::<degree_fahrenheit>(71.5_f32));
+    assert_eq!(state.setpoint_status, SetpointStatus::Confirmed);
+    assert!(!state.expire_pending_setpoint(timeout));
+}
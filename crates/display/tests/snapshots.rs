@@ -0,0 +1,359 @@
+//! Golden-image tests: renders fixed states through `Renderer::draw` into a
+//! `Framebuffer` and compares against `tests/snapshots/*.png`.
+//!
+//! Regenerate goldens after an intended layout change with
+//! `UPDATE_SNAPSHOTS=1 cargo test -p display --target x86_64-unknown-linux-gnu`
+//! and review the images before committing them.
+
+use std::{path::PathBuf, time::Duration};
+
+use display::{
+    framebuffer::Framebuffer,
+    layout::{Position, ScreenLayout},
+    renderer::Renderer,
+    state::{AppState, NetworkStatus, Voltage},
+    widget::Widget,
+};
+use embedded_graphics::{
+    pixelcolor::Gray4,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+};
+use embedded_layout::View;
+use uom::si::{
+    electric_potential::volt, f32::ThermodynamicTemperature,
+    thermodynamic_temperature::degree_fahrenheit,
+};
+
+const LAYOUT: &str = include_str!("../../../layout.toml");
+
+fn fixed_state() -> AppState {
+    let mut state = AppState {
+        updated_counter: 2460,
+        loop_counter: 2460,
+        time_since_boot: Duration::from_secs(73849),
+        batt_voltage: Voltage::new::<volt>(4.0141_f32),
+        state_of_charge: 0.79,
+        initial_state_of_charge: Some(0.98),
+        state_of_charge_change_rate: Some(0.0088),
+        network_status: NetworkStatus::MqttConnected,
+        free_heap_bytes: 189000,
+        ..AppState::new()
+    };
+    state.set_temp_sensor(ThermodynamicTemperature::new::<degree_fahrenheit>(73.2_f32));
+    state.set_temp_setpoint(ThermodynamicTemperature::new::<degree_fahrenheit>(72.5_f32));
+    state
+}
+
+fn render(state: &AppState) -> Framebuffer {
+    render_layout(LAYOUT, state)
+}
+
+fn render_layout(layout: &str, state: &AppState) -> Framebuffer {
+    let layout = ScreenLayout::from_toml(layout).expect("Invalid layout");
+    let mut fb = Framebuffer::new(layout.orientation.size());
+    let mut renderer = Renderer::new(&fb.bounding_box(), &layout);
+    renderer.draw(state, &mut fb).expect("Draw error");
+    fb
+}
+
+/// Bar as wide as the loop counter's last digit, stands in for a downstream widget
+struct CounterBar {
+    origin: Point,
+    digit: u32,
+}
+
+impl View for CounterBar {
+    fn translate_impl(&mut self, by: Point) {
+        self.origin += by;
+    }
+
+    fn bounds(&self) -> Rectangle {
+        Rectangle::new(self.origin, Size::new(200, 20))
+    }
+}
+
+impl<D: DrawTarget<Color = Gray4>> Widget<D> for CounterBar {
+    fn refresh(&mut self, state: &AppState, _render_time: Duration) -> bool {
+        let digit = state.loop_counter % 10;
+        let changed = digit != self.digit;
+        self.digit = digit;
+        changed
+    }
+
+    fn draw(&self, target: &mut D, _dynamic_only: bool) -> Result<Option<Rectangle>, D::Error> {
+        let bounds = self.bounds();
+        bounds
+            .into_styled(PrimitiveStyle::with_fill(Gray4::WHITE))
+            .draw(target)?;
+        Rectangle::new(self.origin, Size::new(self.digit * 20, 20))
+            .into_styled(PrimitiveStyle::with_fill(Gray4::BLACK))
+            .draw(target)?;
+        Ok(Some(bounds))
+    }
+}
+
+fn assert_snapshot(name: &str, actual: &Framebuffer) {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+    let golden_path = dir.join(format!("{name}.png"));
+    let actual_path = dir.join(format!("{name}.actual.png"));
+
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::create_dir_all(&dir).expect("Unable to create snapshot dir");
+        std::fs::write(&golden_path, actual.to_png().expect("PNG encoding"))
+            .expect("Unable to write golden");
+        return;
+    }
+
+    let golden = std::fs::read(&golden_path).unwrap_or_else(|_| {
+        panic!("Missing golden {golden_path:?}, run with UPDATE_SNAPSHOTS=1 to create it")
+    });
+    let golden = Framebuffer::from_png(&golden).expect("Invalid golden image");
+
+    match golden.diff(actual) {
+        Some(0) => {
+            let _ = std::fs::remove_file(&actual_path);
+        }
+        diff => {
+            std::fs::write(&actual_path, actual.to_png().expect("PNG encoding"))
+                .expect("Unable to write actual");
+            panic!("{name} differs from golden ({diff:?} pixels), see {actual_path:?}");
+        }
+    }
+}
+
+#[test]
+fn shipped_layout_builds_renderer() {
+    for profile in ["development", "production"] {
+        for orientation in ["portrait", "landscape"] {
+            let layout = LAYOUT
+                .replace(
+                    r#"profile = "development""#,
+                    &format!(r#"profile = "{profile}""#),
+                )
+                .replace(
+                    r#"orientation = "portrait""#,
+                    &format!(r#"orientation = "{orientation}""#),
+                );
+            let layout = ScreenLayout::from_toml(&layout).expect("Invalid layout");
+            let fb = Framebuffer::new(layout.orientation.size());
+            let _renderer: Renderer<Framebuffer> = Renderer::new(&fb.bounding_box(), &layout);
+        }
+    }
+}
+
+#[test]
+fn thermostat_page() {
+    assert_snapshot("thermostat", &render(&fixed_state()));
+}
+
+#[test]
+fn diagnostics_page() {
+    let state = AppState {
+        active_page: 1,
+        ..fixed_state()
+    };
+    assert_snapshot("diagnostics", &render(&state));
+}
+
+#[test]
+fn battery_page() {
+    let state = AppState {
+        active_page: 2,
+        ..fixed_state()
+    };
+    assert_snapshot("battery", &render(&state));
+}
+
+#[test]
+fn missing_values() {
+    let state = AppState {
+        temp_sensor: None,
+        temp_setpoint: None,
+        ..fixed_state()
+    };
+    assert_snapshot("missing_values", &render(&state));
+}
+
+#[test]
+fn sparkline_with_history() {
+    // Only the readings below, without the one of the fixed state
+    let mut state = AppState {
+        temp_history: AppState::new().temp_history,
+        ..fixed_state()
+    };
+    let start = state.time_since_boot;
+    // Six hours in 10 minute steps: a slow rise with a dip in the middle
+    for i in 0..36 {
+        state.time_since_boot = start + Duration::from_secs(600 * i);
+        let dip = if (12..18).contains(&i) { 2.0 } else { 0.0 };
+        state.set_temp_sensor(ThermodynamicTemperature::new::<degree_fahrenheit>(
+            68.0 + i as f32 * 0.2 - dip,
+        ));
+    }
+    assert_snapshot("sparkline_history", &render(&state));
+}
+
+#[test]
+fn celsius_unit() {
+    let layout = LAYOUT.replace(r#"unit = "fahrenheit""#, r#"unit = "celsius""#);
+    assert_snapshot("celsius", &render_layout(&layout, &fixed_state()));
+}
+
+#[test]
+fn production_profile() {
+    let layout = LAYOUT.replace(r#"profile = "development""#, r#"profile = "production""#);
+    assert_snapshot("production", &render_layout(&layout, &fixed_state()));
+
+    // Diagnostics page is skipped, second page is battery
+    let state = AppState {
+        active_page: 1,
+        ..fixed_state()
+    };
+    assert_snapshot("production_battery", &render_layout(&layout, &state));
+}
+
+#[test]
+fn status_bar_charging_offline() {
+    let state = AppState {
+        state_of_charge: 0.12,
+        charging: true,
+        network_status: NetworkStatus::Error,
+        ..fixed_state()
+    };
+    assert_snapshot("status_bar_charging_offline", &render(&state));
+}
+
+#[test]
+fn landscape_orientation() {
+    let layout = LAYOUT.replace(
+        r#"orientation = "portrait""#,
+        r#"orientation = "landscape""#,
+    );
+    let fb = render_layout(&layout, &fixed_state());
+    assert_eq!(fb.size(), Size::new(960, 540));
+    assert_snapshot("landscape", &fb);
+}
+
+#[test]
+fn low_battery_screen() {
+    let state = AppState {
+        state_of_charge: 0.01,
+        battery_critical: true,
+        ..fixed_state()
+    };
+    assert_snapshot("low_battery", &render(&state));
+}
+
+#[test]
+fn idle_page() {
+    let state = AppState {
+        idle: true,
+        clock: Some(Duration::from_secs(7 * 3600 + 45 * 60)),
+        ..fixed_state()
+    };
+    assert_snapshot("idle", &render(&state));
+}
+
+#[test]
+fn pending_setpoint_outlined() {
+    let mut state = fixed_state();
+    state.adjust_temp_setpoint(
+        1,
+        &ScreenLayout::from_toml(LAYOUT)
+            .expect("Invalid layout")
+            .temperature,
+    );
+    assert_snapshot("pending_setpoint", &render(&state));
+}
+
+#[test]
+fn stale_sensor_dimmed() {
+    let mut state = fixed_state();
+    state.time_since_boot += Duration::from_secs(2 * 3600);
+    assert_snapshot("stale_sensor", &render(&state));
+
+    // Diagnostics show the age
+    state.active_page = 1;
+    assert_snapshot("stale_sensor_diagnostics", &render(&state));
+}
+
+#[test]
+fn seven_segment_sign_and_overflow() {
+    let mut state = fixed_state();
+    state.set_temp_sensor(ThermodynamicTemperature::new::<degree_fahrenheit>(
+        -12.3_f32,
+    ));
+    state.set_temp_setpoint(ThermodynamicTemperature::new::<degree_fahrenheit>(
+        1234.0_f32,
+    ));
+    assert_snapshot("seven_segment_sign_overflow", &render(&state));
+}
+
+#[test]
+fn renamed_table_row_keeps_columns_aligned() {
+    let layout = LAYOUT.replace(r#"label = "Counter""#, r#"label = "Main loop counter""#);
+    let state = AppState {
+        active_page: 1,
+        ..fixed_state()
+    };
+    assert_snapshot("table_long_label", &render_layout(&layout, &state));
+}
+
+#[test]
+fn five_widgets_flow_into_grid() {
+    let widget = |label: &str, source: &str| {
+        format!(
+            "[[pages.widgets]]\nkind = \"seven_segment\"\nlabel = \"{label}\"\nsource = \"{source}\"\nformat = {{ precision = 1 }}\n"
+        )
+    };
+    let layout = [
+        "[[pages]]\nname = \"five\"\n".to_owned(),
+        widget("temp", "temp_sensor"),
+        widget("setpoint", "temp_setpoint"),
+        widget("battery", "batt_voltage"),
+        widget("SOC", "state_of_charge"),
+        widget("heap", "free_heap"),
+    ]
+    .concat();
+    assert_snapshot("five_widgets", &render_layout(&layout, &fixed_state()));
+}
+
+#[test]
+fn custom_widget() {
+    let layout = ScreenLayout::from_toml(LAYOUT).expect("Invalid layout");
+    let mut fb = Framebuffer::new(layout.orientation.size());
+    let mut renderer: Renderer<Framebuffer> = Renderer::new(&fb.bounding_box(), &layout);
+    let bar = Box::new(CounterBar {
+        origin: Point::zero(),
+        digit: 0,
+    });
+    renderer
+        .add_widget("thermostat", Position { row: 2, column: 0 }, bar)
+        .expect("Unknown page");
+    assert!(renderer
+        .add_widget(
+            "missing",
+            Position { row: 0, column: 0 },
+            Box::new(CounterBar {
+                origin: Point::zero(),
+                digit: 0,
+            }),
+        )
+        .is_err());
+
+    renderer.draw(&fixed_state(), &mut fb).expect("Draw error");
+    assert_snapshot("custom_widget", &fb);
+}
+
+#[test]
+fn pgm_roundtrip() {
+    let fb = render(&fixed_state());
+    assert_eq!(Framebuffer::from_pgm(&fb.to_pgm()), Some(fb));
+}
+
+#[test]
+fn png_roundtrip() {
+    let fb = render(&fixed_state());
+    assert_eq!(Framebuffer::from_png(&fb.to_png().unwrap()), Some(fb));
+}
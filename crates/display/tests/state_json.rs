@@ -0,0 +1,56 @@
+//! JSON schema of the diagnostics topic, field names and units must stay stable
+//! and match the README.
+
+use std::time::Duration;
+
+use display::{
+    state::{AppState, NetworkStatus, StateReport, Voltage, STATE_SCHEMA_VERSION},
+    units::{TemperatureSettings, TemperatureUnit},
+};
+use serde_json::json;
+use uom::si::{
+    electric_potential::volt, f32::ThermodynamicTemperature,
+    thermodynamic_temperature::degree_fahrenheit,
+};
+
+#[test]
+fn state_report_schema() {
+    let mut state = AppState {
+        time_since_boot: Duration::from_secs(600),
+        batt_voltage: Voltage::new::<volt>(4.0_f32),
+        network_status: NetworkStatus::MqttConnected,
+        clock: Some(Duration::from_secs(7 * 3600)),
+        ..AppState::new()
+    };
+    state.set_temp_sensor(ThermodynamicTemperature::new::<degree_fahrenheit>(73.0_f32));
+    state.time_since_boot = Duration::from_secs(660);
+
+    let report = serde_json::to_value(StateReport::new(&state)).expect("Serializable state");
+    assert_eq!(report["schema"], json!(STATE_SCHEMA_VERSION));
+    assert_eq!(report["uptime_s"], json!(660));
+    assert_eq!(report["batt_voltage_v"], json!(4.0));
+    assert_eq!(report["network_status"], json!("mqtt_connected"));
+    assert_eq!(report["clock_s"], json!(7 * 3600));
+    // Temperatures go through kelvin, only close to the input
+    let fahrenheit = |v: &serde_json::Value| v.as_f64().expect("Number");
+    assert!((fahrenheit(&report["temp_sensor"]["value_f"]) - 73.0).abs() < 0.01);
+    assert_eq!(report["temp_sensor"]["received_at_s"], json!(600));
+    assert!((fahrenheit(&report["temp_setpoint_f"]) - 72.0).abs() < 0.01);
+    assert_eq!(report["temp_setpoint_confirmed"], json!(null));
+    assert_eq!(report["setpoint_status"], json!({ "state": "confirmed" }));
+    assert!(report.get("temp_history").is_none());
+}
+
+#[test]
+fn temperatures_reported_in_fahrenheit() {
+    let mut state = AppState::new();
+    let celsius = TemperatureSettings {
+        unit: TemperatureUnit::Celsius,
+        ..TemperatureSettings::default()
+    };
+    state.set_temp_setpoint(celsius.unit.temperature(20.0));
+
+    let report = serde_json::to_value(StateReport::new(&state)).expect("Serializable state");
+    let setpoint = report["temp_setpoint_f"].as_f64().expect("Number");
+    assert!((setpoint - 68.0).abs() < 0.01);
+}
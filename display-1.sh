@@ -1,2 +1,2 @@
 #!/bin/bash
-cargo run -p display --profile examples --target x86_64-unknown-linux-gnu --example screen1
\ No newline at end of file
+cargo run -p display --profile examples --target x86_64-unknown-linux-gnu --example screen1 -- "$@"
\ No newline at end of file
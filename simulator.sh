@@ -0,0 +1,2 @@
+#!/bin/bash
+cargo run -p display --profile examples --target x86_64-unknown-linux-gnu --example simulator -- "$@"